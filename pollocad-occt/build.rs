@@ -12,6 +12,7 @@ fn main() {
         .header("src/constants.hpp")
         .parse_callbacks(Box::new(bindgen::CargoCallbacks))
         .rustified_enum("BooleanOp")
+        .rustified_enum("EdgeSelector")
         .constified_enum_module("MouseFlags")
         .generate()
         .expect("Unable to generate bindings");
@@ -25,12 +26,18 @@ fn main() {
     println!("cargo:rustc-link-lib=TKBO");
     println!("cargo:rustc-link-lib=TKBRep");
     println!("cargo:rustc-link-lib=TKernel");
+    println!("cargo:rustc-link-lib=TKFillet");
     println!("cargo:rustc-link-lib=TKMath");
+    println!("cargo:rustc-link-lib=TKMesh");
+    println!("cargo:rustc-link-lib=TKOffset");
     println!("cargo:rustc-link-lib=TKOpenGl");
     println!("cargo:rustc-link-lib=TKPrim");
     println!("cargo:rustc-link-lib=TKService");
+    println!("cargo:rustc-link-lib=TKShHealing");
+    println!("cargo:rustc-link-lib=TKSTEP");
     println!("cargo:rustc-link-lib=TKTopAlgo");
     println!("cargo:rustc-link-lib=TKV3d");
+    println!("cargo:rustc-link-lib=TKXSBase");
 }
 
 // for x in /usr/lib/*TK*.so; do echo $x; nm --dynamic $x|grep y; done|grep -B1 "T "
@@ -13,3 +13,11 @@ enum class BooleanOp {
     Difference = 2,
     Intersection = 3,
 };
+
+enum class EdgeSelector {
+    All = 1,
+    ParallelX = 2,
+    ParallelY = 3,
+    ParallelZ = 4,
+    Top = 5,
+};
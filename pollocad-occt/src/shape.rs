@@ -1,20 +1,163 @@
+use std::ffi::CString;
+use std::path::Path;
+
 use cpp::{cpp, cpp_class};
 
-use crate::{CppResult, Result};
+use crate::{CppResult, Error, Result, VoidResult};
 
 cpp! {{
+    #include <BOPAlgo_CellsBuilder.hxx>
     #include <Bnd_Box.hxx>
     #include <BRepAlgoAPI_Common.hxx>
     #include <BRepAlgoAPI_Cut.hxx>
     #include <BRepAlgoAPI_Fuse.hxx>
     #include <BRepBndLib.hxx>
+    #include <BRepGProp.hxx>
+    #include <BRepTools.hxx>
+    #include <BRepAdaptor_Curve.hxx>
+    #include <BRepAdaptor_Surface.hxx>
     #include <BRepBuilderAPI_Copy.hxx>
+    #include <BRepBuilderAPI_GTransform.hxx>
+    #include <BRepBuilderAPI_MakeEdge.hxx>
+    #include <BRepBuilderAPI_MakeFace.hxx>
+    #include <BRepBuilderAPI_MakePolygon.hxx>
+    #include <BRepBuilderAPI_MakeSolid.hxx>
+    #include <BRepBuilderAPI_MakeVertex.hxx>
+    #include <BRepBuilderAPI_MakeWire.hxx>
+    #include <BRepBuilderAPI_Sewing.hxx>
     #include <BRepBuilderAPI_Transform.hxx>
+    #include <BRepFilletAPI_MakeChamfer.hxx>
+    #include <BRepFilletAPI_MakeFillet.hxx>
+    #include <BRepLib.hxx>
+    #include <BRepMesh_IncrementalMesh.hxx>
+    #include <BRepOffsetAPI_MakeOffset.hxx>
+    #include <BRepOffsetAPI_MakeOffsetShape.hxx>
+    #include <BRepOffsetAPI_MakePipeShell.hxx>
+    #include <BRepOffsetAPI_MakeThickSolid.hxx>
+    #include <BRepOffsetAPI_ThruSections.hxx>
     #include <BRepPrimAPI_MakeBox.hxx>
+    #include <BRepPrimAPI_MakeCone.hxx>
     #include <BRepPrimAPI_MakeCylinder.hxx>
+    #include <BRepPrimAPI_MakePrism.hxx>
+    #include <BRepPrimAPI_MakeRevol.hxx>
+    #include <BRepPrimAPI_MakeSphere.hxx>
+    #include <BRepPrimAPI_MakeTorus.hxx>
+    #include <BRepPrimAPI_MakeWedge.hxx>
+    #include <BRep_Builder.hxx>
+    #include <BRep_Tool.hxx>
+    #include <GProp_GProps.hxx>
+    #include <Poly_Triangulation.hxx>
+    #include <STEPControl_Reader.hxx>
+    #include <STEPControl_Writer.hxx>
+    #include <ShapeUpgrade_UnifySameDomain.hxx>
+    #include <TopExp.hxx>
+    #include <TopExp_Explorer.hxx>
+    #include <TopTools_IndexedMapOfShape.hxx>
+    #include <TopTools_ListOfShape.hxx>
+    #include <TopoDS.hxx>
+    #include <TopoDS_Compound.hxx>
+    #include <TopoDS_Edge.hxx>
+    #include <TopoDS_Face.hxx>
     #include <TopoDS_Shape.hxx>
+    #include <TopoDS_Shell.hxx>
+    #include <TopoDS_Solid.hxx>
+    #include <TopoDS_Wire.hxx>
+    #include <gp_Ax1.hxx>
+    #include <gp_Ax2.hxx>
+    #include <gp_Ax3.hxx>
+    #include <gp_Circ.hxx>
+    #include <gp_GTrsf.hxx>
+    #include <gp_Mat.hxx>
+
+    #include <algorithm>
+    #include <cmath>
+    #include <functional>
+    #include <utility>
+    #include <vector>
 
     #include "protect.hpp"
+
+    // Builds a solid for every face of a planar profile by applying `make` to the face's outer wire
+    // and cutting out what it makes of the holes.
+    static TopoDS_Shape solids_from_wires(
+        const TopoDS_Shape &profile,
+        const std::function<TopoDS_Shape(const TopoDS_Wire &)> &make
+    ) {
+        BRep_Builder builder;
+        TopoDS_Compound result;
+        builder.MakeCompound(result);
+
+        for (TopExp_Explorer faces{profile, TopAbs_FACE}; faces.More(); faces.Next()) {
+            TopoDS_Face face = TopoDS::Face(faces.Current());
+            TopoDS_Wire outer = BRepTools::OuterWire(face);
+
+            TopoDS_Shape solid = make(outer);
+            for (TopExp_Explorer wires{face, TopAbs_WIRE}; wires.More(); wires.Next()) {
+                if (!wires.Current().IsSame(outer)) {
+                    solid = BRepAlgoAPI_Cut{solid, make(TopoDS::Wire(wires.Current()))};
+                }
+            }
+
+            builder.Add(result, solid);
+        }
+
+        return result;
+    }
+
+    static std::vector<TopoDS_Edge> select_edges(const TopoDS_Shape &shape, EdgeSelector selector) {
+        const double tolerance = 1e-6;
+
+        // tight bounds, without the enlargement by the shape tolerance
+        Bnd_Box bounds;
+        BRepBndLib::AddOptimal(shape, bounds, false, false);
+        double xmin, ymin, zmin, xmax, ymax, zmax;
+        bounds.Get(xmin, ymin, zmin, xmax, ymax, zmax);
+
+        // the map makes sure that edges shared by two faces are only visited once
+        TopTools_IndexedMapOfShape edges;
+        TopExp::MapShapes(shape, TopAbs_EDGE, edges);
+
+        std::vector<TopoDS_Edge> result;
+        for (int i = 1; i <= edges.Extent(); i++) {
+            TopoDS_Edge edge = TopoDS::Edge(edges(i));
+            bool selected = true;
+
+            switch (selector) {
+                case EdgeSelector::All:
+                    break;
+                case EdgeSelector::ParallelX:
+                case EdgeSelector::ParallelY:
+                case EdgeSelector::ParallelZ: {
+                    BRepAdaptor_Curve curve{edge};
+                    if (curve.GetType() != GeomAbs_Line) {
+                        selected = false;
+                        break;
+                    }
+
+                    gp_Dir dir = curve.Line().Direction();
+                    double along = selector == EdgeSelector::ParallelX ? dir.X()
+                        : selector == EdgeSelector::ParallelY ? dir.Y()
+                        : dir.Z();
+                    selected = std::abs(along) > 1.0 - tolerance;
+                    break;
+                }
+                case EdgeSelector::Top: {
+                    Bnd_Box edge_bounds;
+                    BRepBndLib::AddOptimal(edge, edge_bounds, false, false);
+                    double ex0, ey0, ez0, ex1, ey1, ez1;
+                    edge_bounds.Get(ex0, ey0, ez0, ex1, ey1, ez1);
+                    selected = ez0 >= zmax - tolerance;
+                    break;
+                }
+            }
+
+            if (selected) {
+                result.push_back(edge);
+            }
+        }
+
+        return result;
+    }
 }}
 
 cpp_class!(pub unsafe struct Shape as "TopoDS_Shape");
@@ -32,6 +175,7 @@ impl CppResult for ShapeResult {
 }
 
 pub use crate::constants::BooleanOp;
+pub use crate::constants::EdgeSelector;
 
 #[derive(Clone, Copy, Debug, Default)]
 #[repr(C)]
@@ -44,6 +188,18 @@ pub struct BoundingBox {
     pub zmax: f64,
 }
 
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub vertices: Vec<[f64; 3]>,
+    pub triangles: Vec<[u32; 3]>,
+}
+
+fn path_cstr(path: &Path) -> Result<CString> {
+    path.to_str()
+        .and_then(|p| CString::new(p).ok())
+        .ok_or_else(|| Error(format!("invalid path: {}", path.display())))
+}
+
 impl Shape {
     pub fn new_cube(x: f64, y: f64, z: f64) -> Result<Shape> {
         cpp!(unsafe [x as "double", y as "double", z as "double"] -> ShapeResult as "CppResult<TopoDS_Shape>" {
@@ -57,16 +213,410 @@ impl Shape {
         }).result()
     }
 
+    pub fn new_sphere(r: f64) -> Result<Shape> {
+        cpp!(unsafe [r as "double"] -> ShapeResult as "CppResult<TopoDS_Shape>" {
+            return protect<TopoDS_Shape>([=] { return BRepPrimAPI_MakeSphere{r}; });
+        }).result()
+    }
+
+    /// Creates a cone along the Z axis with radius `r1` at the bottom and `r2` at the top. The
+    /// radii must differ.
+    pub fn new_cone(r1: f64, r2: f64, h: f64) -> Result<Shape> {
+        cpp!(unsafe [r1 as "double", r2 as "double", h as "double"] -> ShapeResult as "CppResult<TopoDS_Shape>" {
+            return protect<TopoDS_Shape>([=] { return BRepPrimAPI_MakeCone{r1, r2, h}; });
+        }).result()
+    }
+
+    /// Creates a torus around the Z axis with major radius `r1` and minor radius `r2`.
+    pub fn new_torus(r1: f64, r2: f64) -> Result<Shape> {
+        cpp!(unsafe [r1 as "double", r2 as "double"] -> ShapeResult as "CppResult<TopoDS_Shape>" {
+            return protect<TopoDS_Shape>([=] { return BRepPrimAPI_MakeTorus{r1, r2}; });
+        }).result()
+    }
+
+    /// Creates a wedge, which is a box whose top face is shortened to `ltx` along the X axis.
+    pub fn new_wedge(x: f64, y: f64, z: f64, ltx: f64) -> Result<Shape> {
+        cpp!(unsafe [x as "double", y as "double", z as "double", ltx as "double"] -> ShapeResult as "CppResult<TopoDS_Shape>" {
+            return protect<TopoDS_Shape>([=] { return BRepPrimAPI_MakeWedge{x, y, z, ltx}; });
+        }).result()
+    }
+
+    /// Creates a solid from a closed triangle mesh. Coplanar triangles are merged into a single
+    /// face.
+    pub fn from_triangles(vertices: &[[f64; 3]], triangles: &[[u32; 3]]) -> Result<Shape> {
+        let vertices_ptr = vertices.as_ptr() as *const f64;
+        let triangles_ptr = triangles.as_ptr() as *const u32;
+        let len = triangles.len();
+
+        cpp!(unsafe [vertices_ptr as "const double *", triangles_ptr as "const uint32_t *", len as "size_t"] -> ShapeResult as "CppResult<TopoDS_Shape>" {
+            return protect<TopoDS_Shape>([=] {
+                auto point = [=](uint32_t i) {
+                    return gp_Pnt{vertices_ptr[i * 3], vertices_ptr[i * 3 + 1], vertices_ptr[i * 3 + 2]};
+                };
+
+                BRepBuilderAPI_Sewing sewing;
+                for (size_t i = 0; i < len; i++) {
+                    const uint32_t *t = &triangles_ptr[i * 3];
+                    BRepBuilderAPI_MakePolygon polygon{point(t[0]), point(t[1]), point(t[2]), true};
+                    sewing.Add(BRepBuilderAPI_MakeFace{polygon.Wire(), true});
+                }
+                sewing.Perform();
+
+                TopExp_Explorer shells{sewing.SewedShape(), TopAbs_SHELL};
+                if (!shells.More()) {
+                    throw std::logic_error("triangles do not form a closed shell");
+                }
+
+                TopoDS_Solid solid = BRepBuilderAPI_MakeSolid{TopoDS::Shell(shells.Current())};
+                BRepLib::OrientClosedSolid(solid);
+
+                ShapeUpgrade_UnifySameDomain unify{solid};
+                unify.Build();
+                return unify.Shape();
+            });
+        }).result()
+    }
+
+    /// Creates a planar face in the XY plane bounded by the polygon through `points`.
+    pub fn new_polygon(points: &[[f64; 2]]) -> Result<Shape> {
+        let points_ptr = points.as_ptr() as *const f64;
+        let len = points.len();
+
+        cpp!(unsafe [points_ptr as "const double *", len as "size_t"] -> ShapeResult as "CppResult<TopoDS_Shape>" {
+            return protect<TopoDS_Shape>([=] {
+                BRepBuilderAPI_MakePolygon polygon;
+                for (size_t i = 0; i < len; i++) {
+                    polygon.Add(gp_Pnt{points_ptr[i * 2], points_ptr[i * 2 + 1], 0.0});
+                }
+                polygon.Close();
+
+                return TopoDS_Shape{BRepBuilderAPI_MakeFace{polygon.Wire(), true}};
+            });
+        }).result()
+    }
+
+    /// Creates a circular face in the XY plane centered at the origin.
+    pub fn new_circle(r: f64) -> Result<Shape> {
+        cpp!(unsafe [r as "double"] -> ShapeResult as "CppResult<TopoDS_Shape>" {
+            return protect<TopoDS_Shape>([=] {
+                gp_Circ circle{gp_Ax2{gp_Pnt{0.0, 0.0, 0.0}, gp_Dir{0.0, 0.0, 1.0}}, r};
+                TopoDS_Wire wire = BRepBuilderAPI_MakeWire{BRepBuilderAPI_MakeEdge{circle}};
+
+                return TopoDS_Shape{BRepBuilderAPI_MakeFace{wire, true}};
+            });
+        }).result()
+    }
+
+    /// Extrudes the faces of the shape along the Z axis. The profile is rotated clockwise by
+    /// `twist` degrees and scaled by `scale` over the height of the extrusion.
+    pub fn linear_extrude(&self, height: f64, twist: f64, scale: [f64; 2]) -> Result<Shape> {
+        let [sx, sy] = scale;
+
+        cpp!(unsafe [self as "const TopoDS_Shape *", height as "double", twist as "double", sx as "double", sy as "double"] -> ShapeResult as "CppResult<TopoDS_Shape>" {
+            return protect<TopoDS_Shape>([=] {
+                if (twist == 0.0 && sx == 1.0 && sy == 1.0) {
+                    return TopoDS_Shape{BRepPrimAPI_MakePrism{*self, gp_Vec{0.0, 0.0, height}}};
+                }
+
+                // twisted and scaled extrusions are lofted through transformed copies of each
+                // wire, with a section every 10 degrees of twist
+                int sections = std::max(2, (int)std::ceil(std::abs(twist) / 10.0) + 1);
+
+                auto loft = [&](const TopoDS_Wire &wire) {
+                    BRepOffsetAPI_ThruSections thru{true, twist == 0.0};
+
+                    for (int i = 0; i < sections; i++) {
+                        double t = double(i) / (sections - 1);
+                        double ksx = 1.0 + (sx - 1.0) * t;
+                        double ksy = 1.0 + (sy - 1.0) * t;
+
+                        if (ksx == 0.0 && ksy == 0.0) {
+                            thru.AddVertex(BRepBuilderAPI_MakeVertex{gp_Pnt{0.0, 0.0, height * t}});
+                            continue;
+                        }
+
+                        double a = -twist * t * std::acos(-1.0) / 180.0;
+
+                        gp_GTrsf xform{};
+                        xform.SetVectorialPart(gp_Mat{
+                            std::cos(a) * ksx, -std::sin(a) * ksy, 0.0,
+                            std::sin(a) * ksx, std::cos(a) * ksy, 0.0,
+                            0.0, 0.0, 1.0
+                        });
+                        xform.SetTranslationPart(gp_XYZ{0.0, 0.0, height * t});
+
+                        thru.AddWire(TopoDS::Wire(BRepBuilderAPI_GTransform{wire, xform, true}.Shape()));
+                    }
+
+                    return thru.Shape();
+                };
+
+                return solids_from_wires(*self, loft);
+            });
+        }).result()
+    }
+
+    /// Sweeps the faces of the shape along the polyline through `path`. The profile is drawn in
+    /// the XY plane and moved to the start of the path, its Z axis pointing along the first
+    /// segment.
+    pub fn sweep(&self, path: &[[f64; 3]]) -> Result<Shape> {
+        let path_ptr = path.as_ptr() as *const f64;
+        let len = path.len();
+
+        cpp!(unsafe [self as "const TopoDS_Shape *", path_ptr as "const double *", len as "size_t"] -> ShapeResult as "CppResult<TopoDS_Shape>" {
+            return protect<TopoDS_Shape>([=] {
+                BRepBuilderAPI_MakePolygon polygon;
+                for (size_t i = 0; i < len; i++) {
+                    polygon.Add(gp_Pnt{path_ptr[i * 3], path_ptr[i * 3 + 1], path_ptr[i * 3 + 2]});
+                }
+                TopoDS_Wire spine = polygon.Wire();
+
+                gp_Pnt start{path_ptr[0], path_ptr[1], path_ptr[2]};
+                gp_Vec tangent{start, gp_Pnt{path_ptr[3], path_ptr[4], path_ptr[5]}};
+
+                gp_Trsf place;
+                place.SetDisplacement(gp_Ax3{}, gp_Ax3{start, gp_Dir{tangent}});
+                TopoDS_Shape profile = BRepBuilderAPI_Transform{*self, place, true}.Shape();
+
+                return solids_from_wires(profile, [&](const TopoDS_Wire &wire) {
+                    BRepOffsetAPI_MakePipeShell pipe{spine};
+                    pipe.SetMode(false);
+                    pipe.SetTransitionMode(BRepBuilderAPI_RightCorner);
+                    pipe.Add(wire);
+                    pipe.Build();
+                    pipe.MakeSolid();
+                    return pipe.Shape();
+                });
+            });
+        }).result()
+    }
+
+    /// Creates a solid through the planar `sections`, in order. Each section must be a single face
+    /// without holes. With `ruled`, the sections are connected by straight lines instead of a
+    /// smooth surface.
+    pub fn loft(sections: &[&Shape], ruled: bool) -> Result<Shape> {
+        let sections_ptr = sections.as_ptr() as *const *const Shape;
+        let len = sections.len();
+
+        cpp!(unsafe [sections_ptr as "const TopoDS_Shape * const *", len as "size_t", ruled as "bool"] -> ShapeResult as "CppResult<TopoDS_Shape>" {
+            return protect<TopoDS_Shape>([=] {
+                BRepOffsetAPI_ThruSections thru{true, ruled};
+
+                for (size_t i = 0; i < len; i++) {
+                    TopTools_IndexedMapOfShape faces;
+                    TopExp::MapShapes(*sections_ptr[i], TopAbs_FACE, faces);
+                    if (faces.Extent() != 1) {
+                        throw std::logic_error("loft section must be a single face");
+                    }
+
+                    // only the outer wire would be lofted, silently filling any holes
+                    const TopoDS_Face &face = TopoDS::Face(faces(1));
+                    TopTools_IndexedMapOfShape wires;
+                    TopExp::MapShapes(face, TopAbs_WIRE, wires);
+                    if (wires.Extent() != 1) {
+                        throw std::logic_error("loft section must not have holes");
+                    }
+
+                    thru.AddWire(BRepTools::OuterWire(face));
+                }
+
+                return thru.Shape();
+            });
+        }).result()
+    }
+
+    /// Revolves the faces of the shape around the Z axis by `angle` degrees, counterclockwise. The
+    /// profile is first stood up so that its Y axis becomes the Z axis, with X being the radius.
+    pub fn revolve(&self, angle: f64) -> Result<Shape> {
+        cpp!(unsafe [self as "const TopoDS_Shape *", angle as "double"] -> ShapeResult as "CppResult<TopoDS_Shape>" {
+            return protect<TopoDS_Shape>([=] {
+                double pi = std::acos(-1.0);
+                gp_Pnt origin{0.0, 0.0, 0.0};
+
+                gp_Trsf stand_up;
+                stand_up.SetRotation(gp_Ax1{origin, gp_Dir{1.0, 0.0, 0.0}}, pi / 2.0);
+                TopoDS_Shape profile = BRepBuilderAPI_Transform{*self, stand_up, true}.Shape();
+
+                gp_Ax1 axis{origin, gp_Dir{0.0, 0.0, 1.0}};
+                if (std::abs(angle) >= 360.0) {
+                    return TopoDS_Shape{BRepPrimAPI_MakeRevol{profile, axis}};
+                }
+
+                return TopoDS_Shape{BRepPrimAPI_MakeRevol{profile, axis, angle * pi / 180.0}};
+            });
+        }).result()
+    }
+
+    /// Rounds the selected edges with radius `r`. If no edges are selected, the shape is returned
+    /// unchanged.
+    pub fn fillet(&self, r: f64, edges: EdgeSelector) -> Result<Shape> {
+        cpp!(unsafe [self as "const TopoDS_Shape *", r as "double", edges as "EdgeSelector"] -> ShapeResult as "CppResult<TopoDS_Shape>" {
+            return protect<TopoDS_Shape>([=] {
+                std::vector<TopoDS_Edge> selected = select_edges(*self, edges);
+                if (selected.empty()) {
+                    return *self;
+                }
+
+                BRepFilletAPI_MakeFillet fillet{*self};
+                for (const TopoDS_Edge &edge : selected) {
+                    fillet.Add(r, edge);
+                }
+
+                return fillet.Shape();
+            });
+        }).result()
+    }
+
+    /// Bevels the selected edges by distance `d` on both sides. If no edges are selected, the
+    /// shape is returned unchanged.
+    pub fn chamfer(&self, d: f64, edges: EdgeSelector) -> Result<Shape> {
+        cpp!(unsafe [self as "const TopoDS_Shape *", d as "double", edges as "EdgeSelector"] -> ShapeResult as "CppResult<TopoDS_Shape>" {
+            return protect<TopoDS_Shape>([=] {
+                std::vector<TopoDS_Edge> selected = select_edges(*self, edges);
+                if (selected.empty()) {
+                    return *self;
+                }
+
+                BRepFilletAPI_MakeChamfer chamfer{*self};
+                for (const TopoDS_Edge &edge : selected) {
+                    chamfer.Add(d, edge);
+                }
+
+                return chamfer.Shape();
+            });
+        }).result()
+    }
+
+    /// Hollows the shape out, leaving walls of `thickness` inside the original outline. The
+    /// horizontal faces at the very top and bottom can be removed to leave the shell open there.
+    pub fn shell(&self, thickness: f64, open_top: bool, open_bottom: bool) -> Result<Shape> {
+        cpp!(unsafe [self as "const TopoDS_Shape *", thickness as "double", open_top as "bool", open_bottom as "bool"] -> ShapeResult as "CppResult<TopoDS_Shape>" {
+            return protect<TopoDS_Shape>([=] {
+                Bnd_Box bounds;
+                BRepBndLib::AddOptimal(*self, bounds, false, false);
+                double xmin, ymin, zmin, xmax, ymax, zmax;
+                bounds.Get(xmin, ymin, zmin, xmax, ymax, zmax);
+
+                TopTools_ListOfShape open;
+                for (TopExp_Explorer faces{*self, TopAbs_FACE}; faces.More(); faces.Next()) {
+                    TopoDS_Face face = TopoDS::Face(faces.Current());
+                    BRepAdaptor_Surface surface{face};
+                    if (surface.GetType() != GeomAbs_Plane) {
+                        continue;
+                    }
+
+                    gp_Dir normal = surface.Plane().Axis().Direction();
+                    if (face.Orientation() == TopAbs_REVERSED) {
+                        normal.Reverse();
+                    }
+
+                    double z = surface.Plane().Location().Z();
+                    if ((open_top && normal.Z() > 1.0 - 1e-6 && z >= zmax - 1e-6)
+                            || (open_bottom && normal.Z() < -1.0 + 1e-6 && z <= zmin + 1e-6)) {
+                        open.Append(face);
+                    }
+                }
+
+                BRepOffsetAPI_MakeThickSolid thick;
+                thick.MakeThickSolidByJoin(*self, open, -thickness, 1e-6);
+                return thick.Shape();
+            });
+        }).result()
+    }
+
+    /// Grows the shape outwards by `distance`, or shrinks it if `distance` is negative. Edges are
+    /// rounded off.
+    pub fn offset(&self, distance: f64) -> Result<Shape> {
+        cpp!(unsafe [self as "const TopoDS_Shape *", distance as "double"] -> ShapeResult as "CppResult<TopoDS_Shape>" {
+            return protect<TopoDS_Shape>([=] {
+                BRepOffsetAPI_MakeOffsetShape offset;
+                offset.PerformByJoin(*self, distance, 1e-6, BRepOffset_Skin, false, false, GeomAbs_Arc);
+                return offset.Shape();
+            });
+        }).result()
+    }
+
+    /// Grows the faces of a shape lying in the XY plane by `distance` within the plane, or shrinks
+    /// them if `distance` is negative. Corners are rounded off.
+    pub fn offset_2d(&self, distance: f64) -> Result<Shape> {
+        cpp!(unsafe [self as "const TopoDS_Shape *", distance as "double"] -> ShapeResult as "CppResult<TopoDS_Shape>" {
+            return protect<TopoDS_Shape>([=] {
+                TopoDS_Shape result;
+
+                for (TopExp_Explorer faces{*self, TopAbs_FACE}; faces.More(); faces.Next()) {
+                    BRepOffsetAPI_MakeOffset offset{TopoDS::Face(faces.Current()), GeomAbs_Arc};
+                    offset.Perform(distance);
+
+                    // the offset outline and holes come back unordered, so the outline is told
+                    // apart by enclosing the largest area
+                    std::vector<std::pair<double, TopoDS_Face>> pieces;
+                    for (TopExp_Explorer wires{offset.Shape(), TopAbs_WIRE}; wires.More(); wires.Next()) {
+                        TopoDS_Face piece = BRepBuilderAPI_MakeFace{TopoDS::Wire(wires.Current()), true};
+                        GProp_GProps props;
+                        BRepGProp::SurfaceProperties(piece, props);
+                        pieces.emplace_back(std::abs(props.Mass()), piece);
+                    }
+                    if (pieces.empty()) {
+                        continue;
+                    }
+
+                    std::sort(pieces.begin(), pieces.end(), [](const auto &a, const auto &b) {
+                        return a.first > b.first;
+                    });
+
+                    TopoDS_Shape grown = pieces[0].second;
+                    for (size_t i = 1; i < pieces.size(); i++) {
+                        grown = BRepAlgoAPI_Cut{grown, pieces[i].second};
+                    }
+
+                    result = result.IsNull() ? grown : TopoDS_Shape{BRepAlgoAPI_Fuse{result, grown}};
+                }
+
+                if (result.IsNull()) {
+                    throw std::logic_error("shape has no faces to offset");
+                }
+
+                return result;
+            });
+        }).result()
+    }
+
     pub fn transform(&self, matrix: &[f64; 16]) -> Result<Shape> {
         cpp!(unsafe [self as "TopoDS_Shape *", matrix as "double *"] -> ShapeResult as "CppResult<TopoDS_Shape>" {
             return protect<TopoDS_Shape>([=]() {
-                gp_Trsf xform{};
-                xform.SetValues(
-                    matrix[0], matrix[4], matrix[8], matrix[12],
-                    matrix[1], matrix[5], matrix[9], matrix[13],
-                    matrix[2], matrix[6], matrix[10], matrix[14]
-                );
-                return TopoDS_Shape{BRepBuilderAPI_Transform{*self, xform}};
+                // gp_Trsf can only represent rotations, uniform scaling and mirroring, which
+                // means the columns of the linear part are orthogonal and of equal length
+                const double *cols[3] = {&matrix[0], &matrix[4], &matrix[8]};
+                auto dot = [&](int a, int b) {
+                    return cols[a][0] * cols[b][0] + cols[a][1] * cols[b][1] + cols[a][2] * cols[b][2];
+                };
+
+                double len2 = dot(0, 0);
+                double tol = 1e-9 * len2;
+                bool similarity =
+                    std::abs(dot(1, 1) - len2) <= tol && std::abs(dot(2, 2) - len2) <= tol
+                    && std::abs(dot(0, 1)) <= tol && std::abs(dot(0, 2)) <= tol && std::abs(dot(1, 2)) <= tol;
+
+                if (similarity) {
+                    gp_Trsf xform{};
+                    xform.SetValues(
+                        matrix[0], matrix[4], matrix[8], matrix[12],
+                        matrix[1], matrix[5], matrix[9], matrix[13],
+                        matrix[2], matrix[6], matrix[10], matrix[14]
+                    );
+                    return TopoDS_Shape{BRepBuilderAPI_Transform{*self, xform}};
+                }
+
+                // general affine transformations such as non-uniform scaling convert the
+                // geometry to NURBS
+                gp_GTrsf xform{};
+                xform.SetVectorialPart(gp_Mat{
+                    matrix[0], matrix[4], matrix[8],
+                    matrix[1], matrix[5], matrix[9],
+                    matrix[2], matrix[6], matrix[10]
+                });
+                xform.SetTranslationPart(gp_XYZ{matrix[12], matrix[13], matrix[14]});
+                return TopoDS_Shape{BRepBuilderAPI_GTransform{*self, xform, true}};
             });
         }).result()
     }
@@ -87,18 +637,269 @@ impl Shape {
         }).result()
     }
 
+    /// Applies `op` to many shapes in a single operation, which is faster and more robust than
+    /// folding `boolean_op` over them. `Union` fuses all of the shapes, `Difference` removes all
+    /// `tools` from each of the `args`, and `Intersection` keeps what is common to all shapes.
+    /// Both lists must not be empty. With `parallel`, OCCT splits the work over its own worker
+    /// threads, which competes with any parallelism of the caller.
+    pub fn boolean_many(
+        args: &[&Shape],
+        tools: &[&Shape],
+        op: BooleanOp,
+        parallel: bool,
+    ) -> Result<Shape> {
+        let args_ptr = args.as_ptr() as *const *const Shape;
+        let args_len = args.len();
+        let tools_ptr = tools.as_ptr() as *const *const Shape;
+        let tools_len = tools.len();
+
+        cpp!(unsafe [args_ptr as "const TopoDS_Shape * const *", args_len as "size_t", tools_ptr as "const TopoDS_Shape * const *", tools_len as "size_t", op as "BooleanOp", parallel as "bool"] -> ShapeResult as "CppResult<TopoDS_Shape>" {
+            return protect<TopoDS_Shape>([=] {
+                TopTools_ListOfShape arg_list, tool_list;
+                for (size_t i = 0; i < args_len; i++) {
+                    arg_list.Append(*args_ptr[i]);
+                }
+                for (size_t i = 0; i < tools_len; i++) {
+                    tool_list.Append(*tools_ptr[i]);
+                }
+
+                auto run = [&](BRepAlgoAPI_BooleanOperation &algo) {
+                    algo.SetArguments(arg_list);
+                    algo.SetTools(tool_list);
+                    algo.SetRunParallel(parallel);
+                    algo.Build();
+                    if (!algo.IsDone()) {
+                        throw std::logic_error("boolean operation failed");
+                    }
+
+                    return algo.Shape();
+                };
+
+                switch (op) {
+                    default:
+                    case BooleanOp::Union: {
+                        BRepAlgoAPI_Fuse fuse;
+                        return run(fuse);
+                    }
+                    case BooleanOp::Difference: {
+                        BRepAlgoAPI_Cut cut;
+                        return run(cut);
+                    }
+                    case BooleanOp::Intersection: {
+                        // a common operation intersects the union of the arguments with the union
+                        // of the tools, so split everything into cells and keep those inside of
+                        // every shape instead
+                        TopTools_ListOfShape all = arg_list;
+                        all.Append(tool_list);
+
+                        BOPAlgo_CellsBuilder cells;
+                        cells.SetArguments(all);
+                        cells.SetRunParallel(parallel);
+                        cells.Perform();
+                        if (cells.HasErrors()) {
+                            throw std::logic_error("boolean operation failed");
+                        }
+
+                        cells.AddToResult(all, TopTools_ListOfShape{});
+                        cells.RemoveInternalBoundaries();
+                        return cells.Shape();
+                    }
+                }
+            });
+        }).result()
+    }
+
+    /// Returns the exact bounds of the shape, or all zeros if it is empty.
     pub fn bounds(&self) -> BoundingBox {
         let mut r = BoundingBox::default();
 
         unsafe {
             let r = &mut r as *mut _ as *mut f64;
             cpp!([self as "const TopoDS_Shape *", r as "double *"] {
+                // computed from the geometry itself rather than from a tessellation, and without
+                // the enlargement by the shape tolerance
                 Bnd_Box b;
-                BRepBndLib::Add(*self, b);
-                b.Get(r[0], r[1], r[2], r[3], r[4], r[5]);
+                BRepBndLib::AddOptimal(*self, b, false, false);
+                if (!b.IsVoid()) {
+                    b.Get(r[0], r[1], r[2], r[3], r[4], r[5]);
+                }
             })
         }
 
         r
     }
+
+    /// Returns whether the shape consists only of faces lying in the XY plane.
+    pub fn is_planar_xy(&self) -> bool {
+        unsafe {
+            cpp!([self as "const TopoDS_Shape *"] -> bool as "bool" {
+                if (TopExp_Explorer{*self, TopAbs_SOLID}.More() || !TopExp_Explorer{*self, TopAbs_FACE}.More()) {
+                    return false;
+                }
+
+                // exact bounds, so that curved faces bulging out of the plane are caught too
+                Bnd_Box b;
+                BRepBndLib::AddOptimal(*self, b, false, false);
+
+                double xmin, ymin, zmin, xmax, ymax, zmax;
+                b.Get(xmin, ymin, zmin, xmax, ymax, zmax);
+                return std::abs(zmin) < 1e-6 && std::abs(zmax) < 1e-6;
+            })
+        }
+    }
+
+    /// Returns the volume enclosed by the solids of the shape.
+    pub fn volume(&self) -> f64 {
+        unsafe {
+            cpp!([self as "const TopoDS_Shape *"] -> f64 as "double" {
+                GProp_GProps props;
+                BRepGProp::VolumeProperties(*self, props);
+                return props.Mass();
+            })
+        }
+    }
+
+    /// Triangulates the faces of the shape. A copy is meshed, as meshing stores the triangulation
+    /// in the shape, which may be shared with other threads.
+    pub fn mesh(&self, deflection: f64) -> Result<Mesh> {
+        let mut mesh = Mesh::default();
+        let mesh_ptr = &mut mesh;
+
+        cpp!(unsafe [self as "const TopoDS_Shape *", deflection as "double", mesh_ptr as "void *"] -> VoidResult as "CppResult<void>" {
+            return protect<void>([&] {
+                TopoDS_Shape shape = BRepBuilderAPI_Copy{*self, true, false}.Shape();
+                BRepMesh_IncrementalMesh mesher{shape, deflection, false, 0.5};
+
+                std::vector<double> vertices;
+                std::vector<uint32_t> triangles;
+
+                for (TopExp_Explorer ex{shape, TopAbs_FACE}; ex.More(); ex.Next()) {
+                    const TopoDS_Face &face = TopoDS::Face(ex.Current());
+
+                    TopLoc_Location loc;
+                    Handle(Poly_Triangulation) tri = BRep_Tool::Triangulation(face, loc);
+                    if (tri.IsNull()) {
+                        continue;
+                    }
+
+                    uint32_t base = vertices.size() / 3;
+                    gp_Trsf xform = loc.Transformation();
+
+                    for (int i = 1; i <= tri->NbNodes(); i++) {
+                        gp_Pnt p = tri->Node(i).Transformed(xform);
+                        vertices.push_back(p.X());
+                        vertices.push_back(p.Y());
+                        vertices.push_back(p.Z());
+                    }
+
+                    bool reversed = face.Orientation() == TopAbs_REVERSED;
+
+                    for (int i = 1; i <= tri->NbTriangles(); i++) {
+                        Standard_Integer a, b, c;
+                        tri->Triangle(i).Get(a, b, c);
+                        if (reversed) {
+                            std::swap(b, c);
+                        }
+
+                        triangles.push_back(base + a - 1);
+                        triangles.push_back(base + b - 1);
+                        triangles.push_back(base + c - 1);
+                    }
+                }
+
+                const double *vertex_data = vertices.data();
+                size_t vertex_len = vertices.size();
+                const uint32_t *triangle_data = triangles.data();
+                size_t triangle_len = triangles.size();
+
+                rust!(Shape_mesh_fill [
+                    mesh_ptr: &mut Mesh as "void *",
+                    vertex_data: *const f64 as "const double *",
+                    vertex_len: usize as "size_t",
+                    triangle_data: *const u32 as "const uint32_t *",
+                    triangle_len: usize as "size_t"
+                ] {
+                    let (vertices, triangles) = unsafe {
+                        (
+                            std::slice::from_raw_parts(vertex_data, vertex_len),
+                            std::slice::from_raw_parts(triangle_data, triangle_len),
+                        )
+                    };
+
+                    mesh_ptr.vertices.extend(vertices.chunks_exact(3).map(|v| [v[0], v[1], v[2]]));
+                    mesh_ptr.triangles.extend(triangles.chunks_exact(3).map(|t| [t[0], t[1], t[2]]));
+                });
+            });
+        }).result()?;
+
+        Ok(mesh)
+    }
+
+    pub fn read_brep(path: &Path) -> Result<Shape> {
+        let path = path_cstr(path)?;
+        let path = path.as_ptr();
+
+        cpp!(unsafe [path as "const char *"] -> ShapeResult as "CppResult<TopoDS_Shape>" {
+            return protect<TopoDS_Shape>([=] {
+                TopoDS_Shape shape;
+                BRep_Builder builder;
+                if (!BRepTools::Read(shape, path, builder)) {
+                    throw std::logic_error{std::string{"failed to read BREP file "} + path};
+                }
+                return shape;
+            });
+        }).result()
+    }
+
+    pub fn write_brep(&self, path: &Path) -> Result<()> {
+        let path = path_cstr(path)?;
+        let path = path.as_ptr();
+
+        cpp!(unsafe [self as "const TopoDS_Shape *", path as "const char *"] -> VoidResult as "CppResult<void>" {
+            return protect<void>([&] {
+                if (!BRepTools::Write(*self, path)) {
+                    throw std::logic_error{std::string{"failed to write BREP file "} + path};
+                }
+            });
+        }).result()
+    }
+
+    pub fn read_step(path: &Path) -> Result<Shape> {
+        let path = path_cstr(path)?;
+        let path = path.as_ptr();
+
+        cpp!(unsafe [path as "const char *"] -> ShapeResult as "CppResult<TopoDS_Shape>" {
+            return protect<TopoDS_Shape>([=] {
+                STEPControl_Reader reader;
+                if (reader.ReadFile(path) != IFSelect_RetDone) {
+                    throw std::logic_error{std::string{"failed to read STEP file "} + path};
+                }
+
+                reader.TransferRoots();
+                TopoDS_Shape shape = reader.OneShape();
+                if (shape.IsNull()) {
+                    throw std::logic_error{std::string{"no shapes found in STEP file "} + path};
+                }
+                return shape;
+            });
+        }).result()
+    }
+
+    pub fn write_step(&self, path: &Path) -> Result<()> {
+        let path = path_cstr(path)?;
+        let path = path.as_ptr();
+
+        cpp!(unsafe [self as "const TopoDS_Shape *", path as "const char *"] -> VoidResult as "CppResult<void>" {
+            return protect<void>([&] {
+                STEPControl_Writer writer;
+                if (writer.Transfer(*self, STEPControl_AsIs) != IFSelect_RetDone) {
+                    throw std::logic_error{"failed to convert shape to STEP"};
+                }
+
+                if (writer.Write(path) != IFSelect_RetDone) {
+                    throw std::logic_error{std::string{"failed to write STEP file "} + path};
+                }
+            });
+        }).result()
+    }
 }
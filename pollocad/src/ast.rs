@@ -1,17 +1,143 @@
+use std::collections::BTreeSet;
+use std::hash::{Hash, Hasher};
 use std::ops::Range;
 use std::sync::Arc;
 
-#[derive(PartialEq, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct Node {
     pub pos: Range<usize>,
     pub expr: Expr,
+    pub info: NodeInfo,
+}
+
+/// Facts about a node's subtree, computed once when the node is built so that evaluation doesn't
+/// have to walk the subtree again for every call in it.
+#[derive(Clone, Debug)]
+pub struct NodeInfo {
+    /// Hash of the structure of the subtree.
+    pub hash: u64,
+    /// Sorted names of the variables the subtree refers to but does not bind itself.
+    pub free_vars: Vec<String>,
+    /// Sorted names of the functions whose calls decide whether the subtree is heavy. Calls inside
+    /// function definitions, lists and ranges don't count.
+    pub calls: Vec<String>,
 }
 
-/*impl std::hash::Hash for Node {
-    fn hash<H: std::hash::Hasher>(&self, h: &mut H) {
-        self.expr.hash(h);
+impl Node {
+    pub fn new(pos: Range<usize>, expr: Expr) -> Node {
+        let info = NodeInfo::of(&expr);
+        Node { pos, expr, info }
     }
-}*/
+}
+
+/// Nodes are hashed and compared by structure only so that identical code hashes the same no
+/// matter where it is in the source.
+impl Hash for Node {
+    fn hash<H: Hasher>(&self, h: &mut H) {
+        self.info.hash.hash(h);
+    }
+}
+
+impl PartialEq for Node {
+    fn eq(&self, other: &Node) -> bool {
+        self.info.hash == other.info.hash && self.expr == other.expr
+    }
+}
+
+impl NodeInfo {
+    fn of(expr: &Expr) -> NodeInfo {
+        let mut free = BTreeSet::new();
+        let mut calls = BTreeSet::new();
+
+        fn add_free<'a>(
+            free: &mut BTreeSet<String>,
+            nodes: impl IntoIterator<Item = &'a Arc<Node>>,
+            bound: &[&str],
+        ) {
+            for node in nodes {
+                for name in &node.info.free_vars {
+                    if !bound.contains(&name.as_str()) {
+                        free.insert(name.clone());
+                    }
+                }
+            }
+        }
+
+        fn add_calls<'a>(
+            calls: &mut BTreeSet<String>,
+            nodes: impl IntoIterator<Item = &'a Arc<Node>>,
+        ) {
+            for node in nodes {
+                calls.extend(node.info.calls.iter().cloned());
+            }
+        }
+
+        match expr {
+            Expr::Let(let_) => {
+                add_free(&mut free, [&let_.value], &[]);
+                add_free(&mut free, &let_.body, &[&let_.name]);
+                add_calls(&mut calls, [&let_.value].into_iter().chain(&let_.body));
+            }
+            Expr::Call(call) => {
+                free.insert(call.name.clone());
+                calls.insert(call.name.clone());
+
+                let args = call.args.iter().map(|(_, arg)| arg);
+                add_free(&mut free, args.clone().chain(&call.body), &[]);
+                add_calls(&mut calls, args.chain(&call.body));
+            }
+            Expr::Var(name) => {
+                free.insert(name.clone());
+            }
+            Expr::Num(_) | Expr::Str(_) => {}
+            Expr::List(items) => add_free(&mut free, items, &[]),
+            Expr::If(if_) => {
+                let else_body = if_.else_body.iter().flatten();
+                let nodes = [&if_.cond]
+                    .into_iter()
+                    .chain(&if_.then_body)
+                    .chain(else_body);
+                add_free(&mut free, nodes.clone(), &[]);
+                add_calls(&mut calls, nodes);
+            }
+            Expr::For(for_) => {
+                add_free(&mut free, [&for_.iter], &[]);
+                add_free(&mut free, &for_.body, &[&for_.var]);
+                add_calls(&mut calls, &for_.body);
+            }
+            Expr::Range(range) => {
+                add_free(
+                    &mut free,
+                    [&range.start, &range.end].into_iter().chain(&range.step),
+                    &[],
+                );
+            }
+            Expr::Func(func) => {
+                // defaults may refer to the parameters before them
+                let mut bound = vec![func.name.as_str(), "children"];
+                for (name, default) in &func.params {
+                    add_free(&mut free, default, &bound);
+                    bound.push(name);
+                }
+                add_free(&mut free, &func.body, &bound);
+            }
+            Expr::Return(node) => {
+                add_free(&mut free, [node], &[]);
+                add_calls(&mut calls, [node]);
+            }
+        }
+
+        // child nodes hash as their precomputed hashes, so this doesn't walk the subtree
+        let mut h = fxhash::FxHasher64::default();
+        expr.hash(&mut h);
+
+        NodeInfo {
+            hash: h.finish(),
+            free_vars: free.into_iter().collect(),
+            calls: calls.into_iter().collect(),
+        }
+    }
+}
 
 #[derive(PartialEq, Clone, Debug)]
 pub enum Expr {
@@ -19,19 +145,45 @@ pub enum Expr {
     Call(CallExpr),
     Var(String),
     Num(f64),
+    Str(String),
+    List(Vec<Arc<Node>>),
+    If(IfExpr),
+    For(ForExpr),
+    Range(RangeExpr),
+    Func(FuncExpr),
     //UnOp(UnOpExpr),
     //BinOp(BinOpExpr),
     Return(Arc<Node>),
 }
 
-#[derive(PartialEq, Clone, Debug)]
+impl Hash for Expr {
+    fn hash<H: Hasher>(&self, h: &mut H) {
+        std::mem::discriminant(self).hash(h);
+
+        match self {
+            Expr::Let(e) => e.hash(h),
+            Expr::Call(e) => e.hash(h),
+            Expr::Var(name) => name.hash(h),
+            Expr::Num(num) => num.to_bits().hash(h),
+            Expr::Str(s) => s.hash(h),
+            Expr::List(items) => items.hash(h),
+            Expr::If(e) => e.hash(h),
+            Expr::For(e) => e.hash(h),
+            Expr::Range(e) => e.hash(h),
+            Expr::Func(e) => e.hash(h),
+            Expr::Return(node) => node.hash(h),
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug, Hash)]
 pub struct LetExpr {
     pub name: String,
     pub value: Arc<Node>,
     pub body: Vec<Arc<Node>>,
 }
 
-#[derive(PartialEq, Clone, Debug)]
+#[derive(PartialEq, Clone, Debug, Hash)]
 pub struct CallExpr {
     pub name: String,
     //pub pos_args: Vec<Arc<Node>>,
@@ -39,6 +191,34 @@ pub struct CallExpr {
     pub body: Vec<Arc<Node>>,
 }
 
+#[derive(PartialEq, Clone, Debug, Hash)]
+pub struct IfExpr {
+    pub cond: Arc<Node>,
+    pub then_body: Vec<Arc<Node>>,
+    pub else_body: Option<Vec<Arc<Node>>>,
+}
+
+#[derive(PartialEq, Clone, Debug, Hash)]
+pub struct ForExpr {
+    pub var: String,
+    pub iter: Arc<Node>,
+    pub body: Vec<Arc<Node>>,
+}
+
+#[derive(PartialEq, Clone, Debug, Hash)]
+pub struct RangeExpr {
+    pub start: Arc<Node>,
+    pub step: Option<Arc<Node>>,
+    pub end: Arc<Node>,
+}
+
+#[derive(PartialEq, Clone, Debug, Hash)]
+pub struct FuncExpr {
+    pub name: String,
+    pub params: Vec<(String, Option<Arc<Node>>)>,
+    pub body: Vec<Arc<Node>>,
+}
+
 /*#[derive(PartialEq, Clone, Debug)]
 pub struct UnOpExpr {
     pub op: String,
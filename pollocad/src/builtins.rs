@@ -3,8 +3,8 @@ use std::error::Error;
 use std::ops::*;
 use std::sync::Arc;
 
-use crate::geometry::Solid;
-use crate::runtime::{BuiltinFunc, CallCtx, Value};
+use crate::geometry::{EdgeSelector, Solid};
+use crate::runtime::{BuiltinFunc, CallCtx, Value, MAX_RANGE_LEN};
 const EPSILON: f64 = 0.001;
 
 trait MapHelpers {
@@ -28,7 +28,7 @@ impl BuiltinError {
 
 impl std::fmt::Display for BuiltinError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "CGAL error: {}", self.0)
+        f.write_str(&self.0)
     }
 }
 
@@ -45,28 +45,81 @@ impl BuiltinFunc for Cube {
     }
 
     fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
-        let sx = c
-            .named_num("x")?
-            .or(c.pos_num(0, "x")?)
-            .unwrap_or(1.0)
-            .max(EPSILON);
-        let sy = c
-            .named_num("y")?
-            .or(c.pos_num(1, "x")?)
-            .unwrap_or(1.0)
-            .max(EPSILON);
-        let sz = c
-            .named_num("z")?
-            .or(c.pos_num(2, "x")?)
-            .unwrap_or(1.0)
-            .max(EPSILON);
+        let [sx, sy, sz] = box_size(c)?;
 
-        Ok(Value::Solid(Arc::new(Solid::new_cube(
-            sx.max(EPSILON),
-            sy.max(EPSILON),
-            sz.max(EPSILON),
-        )?)))
+        centered(
+            c,
+            Solid::new_cube(sx, sy, sz)?,
+            [-sx / 2.0, -sy / 2.0, -sz / 2.0],
+        )
+    }
+}
+
+/// Reads the size of a box shaped primitive with `N` dimensions, given either as a vector or as
+/// x, y and z.
+fn box_size<const N: usize>(c: &CallCtx) -> Result<[f64; N], Box<dyn Error>> {
+    let size = match c.pos_vec::<N>(0, "size")? {
+        Some(size) => size,
+        None => {
+            let mut size = [1.0; N];
+            for (i, (s, name)) in size.iter_mut().zip(["x", "y", "z"]).enumerate() {
+                *s = c.named_num(name)?.or(c.pos_num(i, name)?).unwrap_or(1.0);
+            }
+            size
+        }
+    };
+
+    Ok(size.map(|s| s.max(EPSILON)))
+}
+
+/// Reads a radius given either directly or as a diameter.
+fn radius(
+    c: &CallCtx,
+    r_name: &str,
+    d_name: &str,
+    what: &str,
+) -> Result<Option<f64>, Box<dyn Error>> {
+    let d = c.named_num(d_name)?;
+    let r = c.named_num(r_name)?;
+    if d.is_some() && r.is_some() {
+        return err(format!(
+            "Cannot specify both diameter and radius for {}",
+            what
+        ));
+    }
+
+    Ok(r.or(d.map(|d| d * 0.5)))
+}
+
+/// Moves the solid by `offset` if `center=true` was given, `offset` being what it takes to center
+/// the primitive.
+fn centered(c: &CallCtx, solid: Solid, offset: [f64; 3]) -> Result<Value, Box<dyn Error>> {
+    let solid = if c.named_bool("center")?.unwrap_or(false) {
+        solid.transform(&cgmath::Matrix4::from_translation(offset.into()))
+    } else {
+        solid
+    };
+
+    Ok(Value::Solid(Arc::new(solid)))
+}
+
+/// Builds a cylinder, or a cone if the radii at the ends differ.
+fn frustum(c: &CallCtx, what: &str, r1: f64, r2: f64) -> Result<Value, Box<dyn Error>> {
+    let r1 = radius(c, "r1", "d1", what)?.unwrap_or(r1);
+    let r2 = radius(c, "r2", "d2", what)?.unwrap_or(r2);
+    if r1 < 0.0 || r2 < 0.0 {
+        return err(format!("Radius of {} must not be negative", what));
     }
+
+    let h = c.named_num("h")?.unwrap_or(1.0); //.max(EPSILON);
+
+    let solid = if r1 == r2 {
+        Solid::new_cylinder(r1, h)?
+    } else {
+        Solid::new_cone(r1, r2, h)?
+    };
+
+    centered(c, solid, [0.0, 0.0, -h / 2.0])
 }
 
 struct Cylinder;
@@ -76,45 +129,427 @@ impl BuiltinFunc for Cylinder {
     }
 
     fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
-        let d = c.named_num("d")?;
-        let r = c.named_num("r")?;
-        if d.is_some() && r.is_some() {
-            return err(
-                "Cannot specify both diameter and radius for cylinder",
-            );
+        let r = radius(c, "r", "d", "cylinder")?.unwrap_or(1.0); //.max(EPSILON);
+
+        frustum(c, "cylinder", r, r)
+    }
+}
+
+struct Cone;
+impl BuiltinFunc for Cone {
+    fn is_heavy(&self) -> bool {
+        true
+    }
+
+    fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
+        frustum(c, "cone", 1.0, 0.0)
+    }
+}
+
+struct Sphere;
+impl BuiltinFunc for Sphere {
+    fn is_heavy(&self) -> bool {
+        true
+    }
+
+    fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
+        let r = radius(c, "r", "d", "sphere")?
+            .or(c.pos_num(0, "r")?)
+            .unwrap_or(1.0);
+
+        // spheres are always centered
+        centered(c, Solid::new_sphere(r.max(EPSILON))?, [0.0; 3])
+    }
+}
+
+struct Torus;
+impl BuiltinFunc for Torus {
+    fn is_heavy(&self) -> bool {
+        true
+    }
+
+    fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
+        let r1 = radius(c, "r1", "d1", "torus")?
+            .or(c.pos_num(0, "r1")?)
+            .unwrap_or(1.0);
+        let r2 = radius(c, "r2", "d2", "torus")?
+            .or(c.pos_num(1, "r2")?)
+            .unwrap_or(0.25);
+
+        if !(r2 > 0.0 && r2 < r1) {
+            return err("Minor radius of torus must be between zero and the major radius");
         }
-        let r = r.or(d.map(|d| d * 0.5)).unwrap_or(1.0); //.max(EPSILON);
 
-        let h = c.named_num("h")?.unwrap_or(1.0); //.max(EPSILON);
+        // tori are always centered
+        centered(c, Solid::new_torus(r1, r2)?, [0.0; 3])
+    }
+}
 
-        Ok(Value::Solid(Arc::new(Solid::new_cylinder(r, h)?)))
+struct Wedge;
+impl BuiltinFunc for Wedge {
+    fn is_heavy(&self) -> bool {
+        true
+    }
+
+    fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
+        let [sx, sy, sz] = box_size(c)?;
+        let ltx = c.named_num("ltx")?.unwrap_or(0.0);
+        if ltx < 0.0 {
+            return err("ltx of wedge must not be negative");
+        }
+
+        centered(
+            c,
+            Solid::new_wedge(sx, sy, sz, ltx)?,
+            [-sx / 2.0, -sy / 2.0, -sz / 2.0],
+        )
     }
 }
 
-fn map_solid(
-    c: &CallCtx,
-    f: impl FnOnce(Solid) -> Result<Solid, Box<dyn Error>>,
-) -> Result<Value, Box<dyn Error>> {
+struct Square;
+impl BuiltinFunc for Square {
+    fn is_heavy(&self) -> bool {
+        true
+    }
+
+    fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
+        let [sx, sy] = box_size(c)?;
+
+        centered(
+            c,
+            Solid::new_polygon(&[[0.0, 0.0], [sx, 0.0], [sx, sy], [0.0, sy]])?,
+            [-sx / 2.0, -sy / 2.0, 0.0],
+        )
+    }
+}
+
+struct Circle;
+impl BuiltinFunc for Circle {
+    fn is_heavy(&self) -> bool {
+        true
+    }
+
+    fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
+        let r = radius(c, "r", "d", "circle")?
+            .or(c.pos_num(0, "r")?)
+            .unwrap_or(1.0);
+
+        Ok(Value::Solid(Arc::new(Solid::new_circle(r.max(EPSILON))?)))
+    }
+}
+
+struct Polygon;
+impl BuiltinFunc for Polygon {
+    fn is_heavy(&self) -> bool {
+        true
+    }
+
+    fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
+        let Some(Value::List(items)) = c.arg(0, "points") else { return err("polygon requires a list of points") };
+        if items.len() < 3 {
+            return err("polygon requires at least 3 points");
+        }
+
+        let points = items
+            .iter()
+            .map(|p| p.to_vec2("points"))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Value::Solid(Arc::new(Solid::new_polygon(&points)?)))
+    }
+}
+
+struct LinearExtrude;
+impl BuiltinFunc for LinearExtrude {
+    fn is_heavy(&self) -> bool {
+        true
+    }
+
+    fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
+        let h = c
+            .named_num("height")?
+            .or(c.pos_num(0, "height")?)
+            .unwrap_or(1.0);
+        if h <= 0.0 {
+            return err("Height of linear_extrude must be positive");
+        }
+
+        let twist = c.named_num("twist")?.unwrap_or(0.0);
+        let scale = match c.named.get("scale") {
+            Some(v @ Value::List(_)) => v.to_vec2("scale")?,
+            Some(v) => [v.to_num("scale")?; 2],
+            None => [1.0; 2],
+        };
+
+        // the top may collapse to a point, but not to a line
+        if scale.iter().any(|s| *s < 0.0) || scale.iter().filter(|s| **s == 0.0).count() == 1 {
+            return err("Scale of linear_extrude must be positive, or zero in both directions");
+        }
+
+        let center = c.named_bool("center")?.unwrap_or(false);
+
+        map_solid(c, |s| {
+            let solid = c.disk_cached("linear_extrude", || {
+                let profile = s.unionize()?;
+
+                if profile
+                    .get_single_shape()
+                    .is_some_and(|s| !s.is_planar_xy())
+                {
+                    return err("Children of linear_extrude must be 2D shapes in the XY plane");
+                }
+
+                Ok(profile.linear_extrude(h, twist, scale)?)
+            })?;
+
+            Ok(if center {
+                solid.transform(&cgmath::Matrix4::from_translation(
+                    [0.0, 0.0, -h / 2.0].into(),
+                ))
+            } else {
+                solid
+            })
+        })
+    }
+}
+
+struct RotateExtrude;
+impl BuiltinFunc for RotateExtrude {
+    fn is_heavy(&self) -> bool {
+        true
+    }
+
+    fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
+        let angle = c
+            .named_num("angle")?
+            .or(c.pos_num(0, "angle")?)
+            .unwrap_or(360.0);
+        if angle == 0.0 {
+            return err("Angle of rotate_extrude must not be zero");
+        }
+
+        map_solid(c, |s| {
+            c.disk_cached("rotate_extrude", || {
+                let profile = s.unionize()?;
+
+                // a profile crossing the axis would sweep through itself
+                if let Some(shape) = profile.get_single_shape() {
+                    let b = shape.bounds();
+                    if b.xmin < -EPSILON && b.xmax > EPSILON {
+                        return err("Profile of rotate_extrude must not cross the Z axis");
+                    }
+                }
+
+                Ok(profile.rotate_extrude(angle.clamp(-360.0, 360.0))?)
+            })
+        })
+    }
+}
+
+struct Sweep;
+impl BuiltinFunc for Sweep {
+    fn is_heavy(&self) -> bool {
+        true
+    }
+
+    fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
+        let Some(Value::List(items)) = c.arg(0, "path") else { return err("sweep requires a path") };
+
+        let path = items
+            .iter()
+            .map(|p| p.to_vec3("path"))
+            .collect::<Result<Vec<_>, _>>()?;
+        if path.len() < 2 {
+            return err("Path of sweep must have at least 2 points");
+        }
+        if path.windows(2).any(|w| w[0] == w[1]) {
+            return err("Path of sweep must not repeat a point");
+        }
+
+        map_solid(c, |s| c.disk_cached("sweep", || Ok(s.sweep(&path)?)))
+    }
+}
+
+struct Loft;
+impl BuiltinFunc for Loft {
+    fn is_heavy(&self) -> bool {
+        true
+    }
+
+    fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
+        let ruled = c.named_bool("ruled")?.unwrap_or(false);
+
+        let sections = solid_children(c)?;
+        if sections.len() < 2 {
+            return err("loft requires at least 2 sections");
+        }
+
+        Ok(Value::Solid(Arc::new(c.disk_cached("loft", || {
+            Ok(Solid::loft(sections.into_iter(), ruled)?)
+        })?)))
+    }
+}
+
+/// Reads which edges to round or bevel from the `edges` argument.
+fn edge_selector(c: &CallCtx) -> Result<EdgeSelector, Box<dyn Error>> {
+    match c.named.get("edges") {
+        None => Ok(EdgeSelector::All),
+        Some(Value::String(s)) => match s.as_ref() {
+            "all" => Ok(EdgeSelector::All),
+            "x" => Ok(EdgeSelector::ParallelX),
+            "y" => Ok(EdgeSelector::ParallelY),
+            "z" => Ok(EdgeSelector::ParallelZ),
+            "top" => Ok(EdgeSelector::Top),
+            _ => err(format!(
+                "Unknown edge selector \"{}\", expected \"all\", \"x\", \"y\", \"z\" or \"top\"",
+                s
+            )),
+        },
+        Some(_) => err("edges must be a string"),
+    }
+}
+
+struct Fillet;
+impl BuiltinFunc for Fillet {
+    fn is_heavy(&self) -> bool {
+        true
+    }
+
+    fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
+        let r = c.named_num("r")?.or(c.pos_num(0, "r")?).unwrap_or(1.0);
+        if r <= 0.0 {
+            return err("Radius of fillet must be positive");
+        }
+
+        let edges = edge_selector(c)?;
+        map_solid(c, |s| c.disk_cached("fillet", || Ok(s.fillet(r, edges)?)))
+    }
+}
+
+struct Chamfer;
+impl BuiltinFunc for Chamfer {
+    fn is_heavy(&self) -> bool {
+        true
+    }
+
+    fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
+        let d = c.named_num("d")?.or(c.pos_num(0, "d")?).unwrap_or(1.0);
+        if d <= 0.0 {
+            return err("Distance of chamfer must be positive");
+        }
+
+        let edges = edge_selector(c)?;
+        map_solid(c, |s| c.disk_cached("chamfer", || Ok(s.chamfer(d, edges)?)))
+    }
+}
+
+/// Reads which faces to leave open from the `open_faces` argument, either a single face name or a
+/// list of them. Returns whether the top and the bottom are open.
+fn open_faces(c: &CallCtx) -> Result<(bool, bool), Box<dyn Error>> {
+    let names = match c.named.get("open_faces") {
+        None => return Ok((false, false)),
+        Some(v @ Value::String(_)) => vec![v.clone()],
+        Some(Value::List(items)) => items.to_vec(),
+        Some(_) => return err("open_faces must be a string or a list of strings"),
+    };
+
+    let (mut top, mut bottom) = (false, false);
+    for name in names {
+        match &name {
+            Value::String(s) if s.as_ref() == "top" => top = true,
+            Value::String(s) if s.as_ref() == "bottom" => bottom = true,
+            _ => return err("open_faces may only contain \"top\" and \"bottom\""),
+        }
+    }
+
+    Ok((top, bottom))
+}
+
+struct Shell;
+impl BuiltinFunc for Shell {
+    fn is_heavy(&self) -> bool {
+        true
+    }
+
+    fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
+        let thickness = c
+            .named_num("thickness")?
+            .or(c.pos_num(0, "thickness")?)
+            .unwrap_or(1.0);
+        if thickness <= 0.0 {
+            return err("Thickness of shell must be positive");
+        }
+
+        let (top, bottom) = open_faces(c)?;
+        map_solid(c, |s| {
+            c.disk_cached("shell", || Ok(s.shell(thickness, top, bottom)?))
+        })
+    }
+}
+
+struct Offset;
+impl BuiltinFunc for Offset {
+    fn is_heavy(&self) -> bool {
+        true
+    }
+
+    fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
+        let distance = c.named_num("distance")?.or(c.pos_num(0, "distance")?);
+        let Some(distance) = distance else { return err("offset requires a distance") };
+
+        // a zero offset is a no-op, which OCCT would reject
+        if distance == 0.0 {
+            return map_solid(c, Ok);
+        }
+
+        map_solid(c, |s| c.disk_cached("offset", || Ok(s.offset(distance)?)))
+    }
+}
+
+fn solid_children<'a>(c: &CallCtx<'a>) -> Result<Vec<&'a Solid>, Box<dyn Error>> {
     c.children
         .iter()
         .map(|c| match c {
             Value::Solid(s) => Ok(s.as_ref()),
             _ => err("Combinators may only have solid values as children"),
         })
-        .collect::<Result<Vec<_>, Box<dyn Error>>>()
+        .collect()
+}
+
+fn map_solid(
+    c: &CallCtx,
+    f: impl FnOnce(Solid) -> Result<Solid, Box<dyn Error>>,
+) -> Result<Value, Box<dyn Error>> {
+    solid_children(c)
         .map(|s| Solid::combine(s.iter().copied()))
         .and_then(f)
         .map(|s| Value::Solid(Arc::new(s)))
 }
 
-struct Union;
+/// The name of the implicit union around the whole program, which scripts can't refer to.
+pub const ROOT_UNION: &str = "<root>";
+
+struct Union {
+    /// Whether a union that takes a boolean operation is stored on disk. The root union isn't,
+    /// since it changes with every edit.
+    disk_cache: bool,
+}
+
 impl BuiltinFunc for Union {
     fn is_heavy(&self) -> bool {
         true
     }
 
     fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
-        map_solid(c, |s| Ok(s.unionize()?))
+        let parallel = c.parallel_booleans();
+
+        map_solid(c, |s| {
+            if self.disk_cache && s.needs_union() {
+                c.disk_cached("union", || Ok(s.unionize_with(parallel)?))
+            } else {
+                Ok(s.unionize_with(parallel)?)
+            }
+        })
     }
 }
 
@@ -125,21 +560,64 @@ impl BuiltinFunc for Intersection {
     }
 
     fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
-        let children = c
-            .children
-            .iter()
-            .map(|c| match c {
-                Value::Solid(s) => Ok(s.as_ref()),
-                _ => err("Combinators may only have solid values as children"),
-            })
-            .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+        let children = solid_children(c)?;
+        let parallel = c.parallel_booleans();
 
-        Ok(Value::Solid(Arc::new(Solid::intersectionize(
-            children.into_iter(),
+        Ok(Value::Solid(Arc::new(
+            c.disk_cached("intersection", || {
+                Ok(Solid::intersectionize(children.into_iter(), parallel)?)
+            })?,
+        )))
+    }
+}
+
+struct Hull;
+impl BuiltinFunc for Hull {
+    fn is_heavy(&self) -> bool {
+        true
+    }
+
+    fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
+        let children = solid_children(c)?;
+
+        Ok(Value::Solid(Arc::new(c.disk_cached(
+            "hull",
+            || match Solid::hull(children.into_iter())? {
+                Some(hull) => Ok(hull),
+                None => err("hull of flat shapes is not supported"),
+            },
         )?)))
     }
 }
 
+struct Minkowski;
+impl BuiltinFunc for Minkowski {
+    fn is_heavy(&self) -> bool {
+        true
+    }
+
+    fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
+        let children = solid_children(c)?;
+
+        let Some((first, rest)) = children.split_first() else { return map_solid(c, Ok) };
+
+        let solid = c.disk_cached("minkowski", || {
+            let Some((second, rest)) = rest.split_first() else { return Ok(first.unionize()?) };
+
+            // the children are passed on as they are, so that a sphere or cylinder among them is
+            // still recognised
+            let mut sum = first.minkowski(second)?;
+            for s in rest {
+                sum = sum.minkowski(s)?;
+            }
+
+            Ok(sum)
+        })?;
+
+        Ok(Value::Solid(Arc::new(solid)))
+    }
+}
+
 struct Anti;
 impl BuiltinFunc for Anti {
     fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
@@ -152,9 +630,13 @@ impl BuiltinFunc for Translate {
     fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
         let mut coord: [f64; 3] = [0.0, 0.0, 0.0];
 
-        for (i, a) in c.pos.iter().enumerate().take(3) {
-            if let Value::Num(n) = a {
-                coord[i] = *n;
+        if let Some(v) = c.pos_vec::<3>(0, "translation")? {
+            coord = v;
+        } else {
+            for (i, a) in c.pos.iter().enumerate().take(3) {
+                if let Value::Num(n) = a {
+                    coord[i] = *n;
+                }
             }
         }
 
@@ -175,6 +657,199 @@ impl BuiltinFunc for Translate {
     }
 }
 
+struct Rotate;
+impl BuiltinFunc for Rotate {
+    fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
+        use cgmath::{Deg, InnerSpace, Matrix4, Vector3};
+
+        let mat = match (c.arg(0, "a"), c.arg(1, "v")) {
+            // Euler angles, applied in X, Y, Z order
+            (Some(a @ Value::List(_)), _) => {
+                let [x, y, z] = a.to_vec3("a")?;
+                Matrix4::from_angle_z(Deg(z))
+                    * Matrix4::from_angle_y(Deg(y))
+                    * Matrix4::from_angle_x(Deg(x))
+            }
+            (Some(a), Some(v)) => {
+                let axis = Vector3::from(v.to_vec3("v")?);
+                if axis.magnitude2() == 0.0 {
+                    return err("Rotation axis must not be zero");
+                }
+
+                Matrix4::from_axis_angle(axis.normalize(), Deg(a.to_num("a")?))
+            }
+            (Some(a), None) => Matrix4::from_angle_z(Deg(a.to_num("a")?)),
+            (None, _) => return err("rotate requires an angle"),
+        };
+
+        map_solid(c, |s| Ok(s.transform(&mat)))
+    }
+}
+
+struct Scale;
+impl BuiltinFunc for Scale {
+    fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
+        let mut factor = match c.arg(0, "v") {
+            Some(v @ Value::List(_)) => v.to_vec3("v")?,
+            Some(v) => [v.to_num("v")?; 3],
+            None => [1.0; 3],
+        };
+
+        for (i, name) in ["x", "y", "z"].iter().enumerate() {
+            if let Some(f) = c.named_num(name)? {
+                factor[i] = f;
+            }
+        }
+
+        if factor.contains(&0.0) {
+            return err("Scale factors must not be zero");
+        }
+
+        let mat = cgmath::Matrix4::from_nonuniform_scale(factor[0], factor[1], factor[2]);
+        map_solid(c, |s| Ok(s.transform(&mat)))
+    }
+}
+
+struct Mirror;
+impl BuiltinFunc for Mirror {
+    fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
+        use cgmath::{InnerSpace, Matrix3, Matrix4, Vector3};
+
+        let Some(v) = c.arg(0, "v") else { return err("mirror requires a plane normal") };
+        let n = Vector3::from(v.to_vec3("v")?);
+        if n.magnitude2() == 0.0 {
+            return err("Mirror plane normal must not be zero");
+        }
+
+        // reflection through the plane through the origin: I - 2 n nᵀ
+        let n = n.normalize();
+        let reflect = |axis: Vector3<f64>| axis - n * (2.0 * n.dot(axis));
+        let mat = Matrix4::from(Matrix3::from_cols(
+            reflect(Vector3::unit_x()),
+            reflect(Vector3::unit_y()),
+            reflect(Vector3::unit_z()),
+        ));
+
+        map_solid(c, |s| Ok(s.transform(&mat)))
+    }
+}
+
+struct MultMatrix;
+impl BuiltinFunc for MultMatrix {
+    fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
+        const MSG: &str = "m must be a list of 3 or 4 rows of 4 numbers";
+
+        let Some(Value::List(rows)) = c.arg(0, "m") else { return err(MSG) };
+        if !(3..=4).contains(&rows.len()) {
+            return err(MSG);
+        }
+
+        let mut m = [[0.0, 0.0, 0.0, 1.0]; 4];
+        for (row, value) in m.iter_mut().zip(rows.iter()) {
+            let Value::List(items) = value else { return err(MSG) };
+            if items.len() != 4 {
+                return err(MSG);
+            }
+
+            for (x, item) in row.iter_mut().zip(items.iter()) {
+                *x = item.to_num("m")?;
+            }
+        }
+
+        if m[3] != [0.0, 0.0, 0.0, 1.0] {
+            return err(
+                "Only affine transformations are supported (last row must be [0, 0, 0, 1])",
+            );
+        }
+
+        // cgmath takes the matrix column by column, while it is written row by row
+        #[rustfmt::skip]
+        let mat = cgmath::Matrix4::new(
+            m[0][0], m[1][0], m[2][0], m[3][0],
+            m[0][1], m[1][1], m[2][1], m[3][1],
+            m[0][2], m[1][2], m[2][2], m[3][2],
+            m[0][3], m[1][3], m[2][3], m[3][3],
+        );
+
+        map_solid(c, |s| Ok(s.transform(&mat)))
+    }
+}
+
+/// Reads the number of copies for a pattern.
+fn pattern_count(c: &CallCtx) -> Result<usize, Box<dyn Error>> {
+    let count = c
+        .named_num("count")?
+        .or(c.pos_num(0, "count")?)
+        .unwrap_or(1.0);
+    if count.fract() != 0.0 || count < 1.0 {
+        return err("count must be a positive whole number");
+    }
+    if count > MAX_RANGE_LEN {
+        return err(format!("count must not exceed {}", MAX_RANGE_LEN));
+    }
+
+    Ok(count as usize)
+}
+
+/// Combines copies of the children, one for each transformation. The copies share their shapes.
+fn pattern(
+    c: &CallCtx,
+    mats: impl Iterator<Item = cgmath::Matrix4<f64>>,
+) -> Result<Value, Box<dyn Error>> {
+    map_solid(c, |s| {
+        let copies: Vec<_> = mats.map(|mat| s.transform(&mat)).collect();
+        Ok(Solid::combine(copies.iter()))
+    })
+}
+
+struct LinearPattern;
+impl BuiltinFunc for LinearPattern {
+    fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
+        let count = pattern_count(c)?;
+        let Some(step) = c.arg(1, "step") else { return err("linear_pattern requires a step") };
+        let step = cgmath::Vector3::from(step.to_vec3("step")?);
+
+        pattern(
+            c,
+            (0..count).map(|i| cgmath::Matrix4::from_translation(step * i as f64)),
+        )
+    }
+}
+
+struct CircularPattern;
+impl BuiltinFunc for CircularPattern {
+    fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
+        use cgmath::{Deg, InnerSpace, Matrix4, Vector3};
+
+        let count = pattern_count(c)?;
+        let angle = c
+            .named_num("angle")?
+            .or(c.pos_num(1, "angle")?)
+            .unwrap_or(360.0);
+        let axis = match c.arg(2, "axis") {
+            Some(v) => Vector3::from(v.to_vec3("axis")?),
+            None => Vector3::unit_z(),
+        };
+        if axis.magnitude2() == 0.0 {
+            return err("Pattern axis must not be zero");
+        }
+
+        // a full circle would put the last copy on top of the first, so the copies are spread
+        // evenly instead, while a partial arc has copies at both ends
+        let step = if angle.abs() >= 360.0 || count == 1 {
+            angle / count as f64
+        } else {
+            angle / (count - 1) as f64
+        };
+
+        let axis = axis.normalize();
+        pattern(
+            c,
+            (0..count).map(|i| Matrix4::from_axis_angle(axis, Deg(step * i as f64))),
+        )
+    }
+}
+
 struct NumOp(fn(a: f64, b: f64) -> f64);
 impl BuiltinFunc for NumOp {
     fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
@@ -187,17 +862,561 @@ impl BuiltinFunc for NumOp {
     }
 }
 
+struct Echo;
+impl BuiltinFunc for Echo {
+    fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
+        let mut named = c.named.iter().collect::<Vec<_>>();
+        named.sort_by_key(|(name, _)| name.as_str());
+
+        let msg = c
+            .pos
+            .iter()
+            .map(|v| match v {
+                Value::String(s) => s.to_string(),
+                v => v.to_string(),
+            })
+            .chain(named.iter().map(|(name, v)| format!("{} = {}", name, v)))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        c.log(format!("ECHO: {}", msg));
+
+        Ok(Value::Undefined)
+    }
+}
+
+struct Index;
+impl BuiltinFunc for Index {
+    fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
+        assert!(c.pos.len() == 2);
+
+        let Value::List(items) = &c.pos[0] else { return err("only lists can be indexed") };
+        let Value::Num(index) = c.pos[1] else { return err("index must be a number") };
+
+        if index.fract() != 0.0 || index < 0.0 || index >= items.len() as f64 {
+            return err(format!(
+                "index {} out of range for list of length {}",
+                index,
+                items.len()
+            ));
+        }
+
+        Ok(items[index as usize].clone())
+    }
+}
+
+struct UnaryNumOp(fn(a: f64) -> f64);
+impl BuiltinFunc for UnaryNumOp {
+    fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
+        assert!(c.pos.len() == 1);
+
+        let Value::Num(a) = c.pos[0] else { return err("not a number") };
+
+        Ok(Value::Num(self.0(a)))
+    }
+}
+
+struct CmpOp(fn(a: &f64, b: &f64) -> bool);
+impl BuiltinFunc for CmpOp {
+    fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
+        assert!(c.pos.len() == 2);
+
+        let Value::Num(a) = c.pos[0] else { return err("not a number") };
+        let Value::Num(b) = c.pos[1] else { return err("not a number") };
+
+        Ok(Value::Bool(self.0(&a, &b)))
+    }
+}
+
+struct EqOp(bool);
+impl BuiltinFunc for EqOp {
+    fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
+        assert!(c.pos.len() == 2);
+
+        Ok(Value::Bool(c.pos[0].equals(&c.pos[1]) == self.0))
+    }
+}
+
+struct Not;
+impl BuiltinFunc for Not {
+    fn call(&self, c: &mut CallCtx) -> Result<Value, Box<dyn Error>> {
+        assert!(c.pos.len() == 1);
+
+        let Value::Bool(a) = c.pos[0] else { return err("not a boolean") };
+
+        Ok(Value::Bool(!a))
+    }
+}
+
 pub fn get_builtins() -> HashMap<String, Value> {
     let mut builtins = HashMap::new();
     builtins.add_func("cube", Cube);
     builtins.add_func("cylinder", Cylinder);
-    builtins.add_func("union", Union);
+    builtins.add_func("cone", Cone);
+    builtins.add_func("sphere", Sphere);
+    builtins.add_func("torus", Torus);
+    builtins.add_func("wedge", Wedge);
+    builtins.add_func("square", Square);
+    builtins.add_func("circle", Circle);
+    builtins.add_func("polygon", Polygon);
+    builtins.add_func("linear_extrude", LinearExtrude);
+    builtins.add_func("rotate_extrude", RotateExtrude);
+    builtins.add_func("sweep", Sweep);
+    builtins.add_func("loft", Loft);
+    builtins.add_func("fillet", Fillet);
+    builtins.add_func("chamfer", Chamfer);
+    builtins.add_func("shell", Shell);
+    builtins.add_func("offset", Offset);
+    builtins.add_func("union", Union { disk_cache: true });
+    builtins.add_func(ROOT_UNION, Union { disk_cache: false });
     builtins.add_func("intersection", Intersection);
+    builtins.add_func("hull", Hull);
+    builtins.add_func("minkowski", Minkowski);
     builtins.add_func("anti", Anti);
     builtins.add_func("translate", Translate);
+    builtins.add_func("rotate", Rotate);
+    builtins.add_func("scale", Scale);
+    builtins.add_func("mirror", Mirror);
+    builtins.add_func("multmatrix", MultMatrix);
+    builtins.add_func("linear_pattern", LinearPattern);
+    builtins.add_func("circular_pattern", CircularPattern);
+    builtins.add_func("echo", Echo);
     builtins.add_func("+", NumOp(f64::add));
     builtins.add_func("-", NumOp(f64::sub));
     builtins.add_func("*", NumOp(f64::mul));
     builtins.add_func("/", NumOp(f64::div));
+    builtins.add_func("[]", Index);
+    builtins.add_func("unary-", UnaryNumOp(f64::neg));
+    builtins.add_func("unary+", UnaryNumOp(|a| a));
+    builtins.add_func("<", CmpOp(f64::lt));
+    builtins.add_func("<=", CmpOp(f64::le));
+    builtins.add_func(">", CmpOp(f64::gt));
+    builtins.add_func(">=", CmpOp(f64::ge));
+    builtins.add_func("==", EqOp(true));
+    builtins.add_func("!=", EqOp(false));
+    builtins.add_func("!", Not);
+    builtins.insert("true".to_string(), Value::Bool(true));
+    builtins.insert("false".to_string(), Value::Bool(false));
     builtins
 }
+
+#[cfg(test)]
+mod test {
+    use std::f64::consts::{FRAC_1_SQRT_2, PI, SQRT_2};
+
+    use crate::runtime::test::{assert_bounds, assert_solid, assert_volume, eval};
+
+    #[test]
+    fn test_transforms() {
+        assert_bounds(
+            "rotate(90) cube([1, 2, 3]);",
+            [-2.0, 0.0, 0.0, 0.0, 1.0, 3.0],
+        );
+        assert_bounds(
+            "rotate([90, 0, 0]) cube([1, 2, 3]);",
+            [0.0, -3.0, 0.0, 1.0, 0.0, 2.0],
+        );
+        assert_bounds(
+            "rotate([90, 0, 90]) cube([1, 2, 3]);",
+            [0.0, 0.0, 0.0, 3.0, 1.0, 2.0],
+        );
+        assert_bounds(
+            "rotate(a=90, v=[0, 1, 0]) cube([1, 2, 3]);",
+            [0.0, 0.0, -1.0, 3.0, 2.0, 0.0],
+        );
+        assert_bounds(
+            "rotate(45) cube();",
+            [-FRAC_1_SQRT_2, 0.0, 0.0, FRAC_1_SQRT_2, SQRT_2, 1.0],
+        );
+        assert_bounds("scale(2) cube();", [0.0, 0.0, 0.0, 2.0, 2.0, 2.0]);
+        assert_bounds("scale([1, 2, 3]) cube();", [0.0, 0.0, 0.0, 1.0, 2.0, 3.0]);
+        assert_bounds("scale(z=-1) cube();", [0.0, 0.0, -1.0, 1.0, 1.0, 0.0]);
+        assert_bounds(
+            "mirror([1, 0, 0]) cube([1, 2, 3]);",
+            [-1.0, 0.0, 0.0, 0.0, 2.0, 3.0],
+        );
+        assert_bounds(
+            "mirror(v=[1, 1]) cube([1, 2, 3]);",
+            [-2.0, -1.0, 0.0, 0.0, 0.0, 3.0],
+        );
+        assert_bounds(
+            "multmatrix([[1, 0, 0, 5], [0, 1, 0, 0], [0, 0, 1, 0]]) cube();",
+            [5.0, 0.0, 0.0, 6.0, 1.0, 1.0],
+        );
+        assert_bounds(
+            "multmatrix([[2, 1, 0, 0], [0, 1, 0, 0], [0, 0, 1, 0], [0, 0, 0, 1]]) cube();",
+            [0.0, 0.0, 0.0, 3.0, 1.0, 1.0],
+        );
+        assert_volume("scale([1, 2, 3]) cube();", 6.0);
+        assert_volume(
+            "multmatrix([[2, 1, 0, 0], [0, 1, 0, 0], [0, 0, 1, 0], [0, 0, 0, 1]]) cube();",
+            2.0,
+        );
+
+        assert!(eval("rotate() cube();").is_err());
+        assert!(eval("translate([1, 2]) cube();").is_err());
+        assert!(eval("rotate(a=30, v=[0, 0, 0]) cube();").is_err());
+        assert!(eval("scale([1, 0, 1]) cube();").is_err());
+        assert!(eval("mirror([0, 0, 0]) cube();").is_err());
+        assert!(eval("multmatrix([[1, 0, 0], [0, 1, 0], [0, 0, 1]]) cube();").is_err());
+        assert!(eval(
+            "multmatrix([[1, 0, 0, 0], [0, 1, 0, 0], [0, 0, 1, 0], [1, 0, 0, 1]]) cube();"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_patterns() {
+        assert_solid(
+            "circular_pattern(6) translate(x=10) cylinder(r=1); circular_pattern(1) cube();",
+        );
+
+        assert_bounds(
+            "linear_pattern(5, [10, 0, 0]) cube(2);",
+            [0.0, 0.0, 0.0, 42.0, 1.0, 1.0],
+        );
+        assert_bounds(
+            "linear_pattern(count=3, step=[0, 5]) cube();",
+            [0.0, 0.0, 0.0, 1.0, 11.0, 1.0],
+        );
+        assert_volume("linear_pattern(3, [2, 0, 0]) cube();", 3.0);
+        assert_volume("linear_pattern(2, [0.5, 0, 0]) cube();", 1.5);
+        assert_bounds(
+            "circular_pattern(4) translate(x=10) cube(center=true);",
+            [-10.5, -10.5, -0.5, 10.5, 10.5, 0.5],
+        );
+        assert_bounds(
+            "circular_pattern(3, 90, [1, 0, 0]) cube();",
+            [0.0, -1.0, 0.0, 1.0, 1.0, SQRT_2],
+        );
+
+        assert!(eval("linear_pattern(2) cube();").is_err());
+        assert!(eval("linear_pattern(2.5, [1, 0, 0]) cube();").is_err());
+        assert!(eval("circular_pattern(0) cube();").is_err());
+        assert!(eval("linear_pattern(1e7, [1, 0, 0]) cube();").is_err());
+        assert!(eval("circular_pattern(4, axis=[0, 0, 0]) cube();").is_err());
+    }
+
+    #[test]
+    fn test_primitives() {
+        assert_bounds("cube(2, center=true);", [-1.0, -0.5, -0.5, 1.0, 0.5, 0.5]);
+        assert_bounds(
+            "cube([1, 2, 3], center=false);",
+            [0.0, 0.0, 0.0, 1.0, 2.0, 3.0],
+        );
+        assert_bounds(
+            "cylinder(d=2, h=3, center=true);",
+            [-1.0, -1.0, -1.5, 1.0, 1.0, 1.5],
+        );
+        assert_volume("cylinder(d=2, h=3);", PI * 3.0);
+        assert_bounds("cylinder(r1=1, r2=2);", [-2.0, -2.0, 0.0, 2.0, 2.0, 1.0]);
+        assert_volume("cylinder(r1=1, r2=2);", PI * 7.0 / 3.0);
+        assert_volume("cylinder(d1=2, r2=0);", PI / 3.0);
+        assert_bounds("cone(h=2);", [-1.0, -1.0, 0.0, 1.0, 1.0, 2.0]);
+        assert_bounds(
+            "cone(r1=2, d2=1, center=true);",
+            [-2.0, -2.0, -0.5, 2.0, 2.0, 0.5],
+        );
+        assert_bounds("sphere();", [-1.0, -1.0, -1.0, 1.0, 1.0, 1.0]);
+        assert_volume("sphere(2);", PI * 32.0 / 3.0);
+        assert_bounds(
+            "sphere(d=3, center=true);",
+            [-1.5, -1.5, -1.5, 1.5, 1.5, 1.5],
+        );
+        assert_bounds("torus(5, 1);", [-6.0, -6.0, -1.0, 6.0, 6.0, 1.0]);
+        assert_volume("torus(d1=10, r2=1);", 2.0 * PI * PI * 5.0);
+        assert_bounds(
+            "wedge([4, 2, 1], ltx=1, center=true);",
+            [-2.0, -1.0, -0.5, 2.0, 1.0, 0.5],
+        );
+        assert_volume("wedge([4, 2, 1], ltx=1);", 5.0);
+        assert_volume("wedge();", 0.5);
+
+        assert!(eval("cylinder(r=1, d=2);").is_err());
+        assert!(eval("cone(r1=-1);").is_err());
+        assert!(eval("sphere(r=1, d=2);").is_err());
+        assert!(eval("torus(1, 2);").is_err());
+        assert!(eval("wedge(ltx=-1);").is_err());
+        assert!(eval("cube(center=1);").is_err());
+
+        // sizes given as vectors need one number per dimension
+        for (code, reason) in [
+            ("cube([2, 3]);", "size must be a vector of 3 numbers"),
+            ("wedge([1, 2, 3, 4]);", "size must be a vector of 3 numbers"),
+            ("square([1, 2, 3]);", "size must be a vector of 2 numbers"),
+        ] {
+            match eval(code) {
+                Err(e) => assert_eq!(e.reason(), reason),
+                Ok(_) => panic!("{} did not fail", code),
+            }
+        }
+    }
+
+    #[test]
+    fn test_2d() {
+        assert_solid("square(2); square([1, 2], center=true); circle(d=3);");
+        assert_solid("polygon([[0, 0], [1, 0], [0, 1]]); anti() circle(0.5);");
+
+        assert_bounds("square(2);", [0.0, 0.0, 0.0, 2.0, 1.0, 0.0]);
+        assert_bounds(
+            "square([1, 2], center=true);",
+            [-0.5, -1.0, 0.0, 0.5, 1.0, 0.0],
+        );
+        assert_bounds("circle(d=3);", [-1.5, -1.5, 0.0, 1.5, 1.5, 0.0]);
+        assert_bounds(
+            "polygon([[0, 0], [2, 0], [0, 1]]);",
+            [0.0, 0.0, 0.0, 2.0, 1.0, 0.0],
+        );
+
+        assert_bounds(
+            "linear_extrude(5) square(2, center=true);",
+            [-1.0, -0.5, 0.0, 1.0, 0.5, 5.0],
+        );
+        assert_volume("linear_extrude(5) square(2, center=true);", 10.0);
+        assert_bounds(
+            "linear_extrude(height=4, center=true) circle();",
+            [-1.0, -1.0, -2.0, 1.0, 1.0, 2.0],
+        );
+        assert_volume("linear_extrude(height=4, center=true) circle();", 4.0 * PI);
+
+        // twisting shears the cross-sections without changing their area
+        assert_volume(
+            "linear_extrude(height=5, twist=90) square([2, 2], center=true);",
+            20.0,
+        );
+        assert_volume(
+            "linear_extrude(3, scale=0.5) square([2, 2], center=true);",
+            7.0,
+        );
+        assert_volume(
+            "linear_extrude(3, scale=0) square([2, 2], center=true);",
+            4.0,
+        );
+        assert_bounds(
+            "linear_extrude(1, scale=[2, 0.5]) circle();",
+            [-2.0, -1.0, 0.0, 2.0, 1.0, 1.0],
+        );
+        assert_volume(
+            "linear_extrude(1, scale=[2, 0.5]) circle();",
+            PI * 13.0 / 12.0,
+        );
+        assert_volume(
+            "linear_extrude(2) { square([4, 4], center=true); anti() circle(); }",
+            2.0 * (16.0 - PI),
+        );
+
+        assert!(eval("polygon([[0, 0], [1, 0]]);").is_err());
+        assert!(eval("polygon([[0, 0], [1, 0], [1, 1, 1]]);").is_err());
+        assert!(eval("circle(r=1, d=2);").is_err());
+        assert!(eval("linear_extrude(0) square();").is_err());
+        assert!(eval("linear_extrude(1, scale=[1, 0]) square();").is_err());
+        assert!(eval("linear_extrude(1, scale=-1) square();").is_err());
+        assert!(eval("linear_extrude(1) cube();").is_err());
+        assert!(eval("linear_extrude(1) { square(); translate(z=1) square(); }").is_err());
+        assert!(eval("linear_extrude(1) rotate([90, 0, 0]) square();").is_err());
+
+        assert_bounds(
+            "rotate_extrude() translate(x=2) circle(0.5);",
+            [-2.5, -2.5, -0.5, 2.5, 2.5, 0.5],
+        );
+        assert_volume(
+            "rotate_extrude() translate(x=2) circle(0.5);",
+            2.0 * PI * PI * 2.0 * 0.25,
+        );
+        assert_bounds(
+            "rotate_extrude(90) square([1, 2]);",
+            [0.0, 0.0, 0.0, 1.0, 1.0, 2.0],
+        );
+        assert_volume("rotate_extrude(90) square([1, 2]);", PI / 2.0);
+        assert_bounds(
+            "rotate_extrude(-90) square([1, 2]);",
+            [0.0, -1.0, 0.0, 1.0, 0.0, 2.0],
+        );
+
+        assert!(eval("rotate_extrude(0) square();").is_err());
+        assert!(eval("rotate_extrude() square(2, center=true);").is_err());
+    }
+
+    #[test]
+    fn test_hull() {
+        assert_solid("hull() { cube(2); translate(x=10) sphere(1); }");
+        assert_solid("hull() {}");
+
+        assert_bounds(
+            "hull() { cube(); translate(x=2) cube(); }",
+            [0.0, 0.0, 0.0, 3.0, 1.0, 1.0],
+        );
+        assert_volume("hull() { cube(); translate(x=2) cube(); }", 3.0);
+        assert_bounds(
+            "hull() { cube(2); translate(x=10) sphere(1); }",
+            [0.0, -1.0, -1.0, 11.0, 1.0, 1.0],
+        );
+        assert_volume(
+            "hull() { for (x = [0, 10]) translate(x=x) cylinder(r=2, h=1); }",
+            4.0 * PI + 40.0,
+        );
+
+        assert!(eval("hull() { square(2); translate(x=5) circle(1); }").is_err());
+        assert!(eval("hull() { cube(); 1; }").is_err());
+    }
+
+    #[test]
+    fn test_minkowski() {
+        assert_solid("minkowski() { cube(10); sphere(1); }");
+        assert_solid("minkowski() { translate(x=5) sphere(1); cube(10); cylinder(r=1, h=1); }");
+        assert_solid("minkowski() { cube(10); cylinder(r=1, h=1); } minkowski() { cube(); }");
+        assert_solid("minkowski() { square(10); cylinder(r=1, h=1); }");
+
+        // the sum with a sphere is exact whichever child it is
+        let rounded = [-1.0, -1.0, -1.0, 11.0, 11.0, 11.0];
+        assert_bounds("minkowski() { cube([10, 10, 10]); sphere(1); }", rounded);
+        assert_bounds("minkowski() { sphere(1); cube([10, 10, 10]); }", rounded);
+        assert_bounds(
+            "minkowski() { translate(x=5) sphere(1); cube([10, 10, 10]); }",
+            [4.0, -1.0, -1.0, 16.0, 11.0, 11.0],
+        );
+
+        // and so is the sum of a 2D shape with a cylinder, keeping the notch of this L shape that
+        // the hull fallback would fill in
+        let l_shape = "polygon([[0, 0], [4, 0], [4, 1], [1, 1], [1, 4], [0, 4]])";
+        let area = 7.0 + 16.0 * 0.5 + 5.0 * PI / 4.0 * 0.25 - 0.25;
+        assert_volume(
+            &format!("minkowski() {{ {}; cylinder(r=0.5, h=2); }}", l_shape),
+            2.0 * area,
+        );
+        assert_volume(
+            &format!("minkowski() {{ cylinder(r=0.5, h=2); {}; }}", l_shape),
+            2.0 * area,
+        );
+
+        // a 2D shape with a sphere gives a rounded slab, not a grown face
+        assert_bounds(
+            "minkowski() { square([10, 10]); sphere(1); }",
+            [-1.0, -1.0, -1.0, 11.0, 11.0, 1.0],
+        );
+
+        // other sums of non-convex shapes would come out as their hulls
+        for code in [
+            format!("minkowski() {{ linear_extrude(1) {}; cylinder(r=0.5, h=2); }}", l_shape),
+            format!("minkowski() {{ {}; sphere(1); }}", l_shape),
+        ] {
+            match eval(&code) {
+                Err(e) => assert!(e.reason().contains("non-convex"), "{}: {}", code, e.reason()),
+                Ok(_) => panic!("{} did not fail", code),
+            }
+        }
+
+        assert!(eval("minkowski() { square(10); circle(1); }").is_err());
+        assert!(eval("minkowski() { cube(); 1; }").is_err());
+    }
+
+    #[test]
+    fn test_sweep_loft() {
+        assert_bounds(
+            "sweep([[0, 0, 0], [0, 0, 10], [10, 0, 10]]) circle(1);",
+            [-1.0, -1.0, 0.0, 10.0, 1.0, 11.0],
+        );
+        assert_bounds(
+            "loft() { circle(2); translate(z=3) circle(1); }",
+            [-2.0, -2.0, 0.0, 2.0, 2.0, 3.0],
+        );
+        assert_volume(
+            "loft(ruled=true) { circle(2); translate(z=3) circle(1); }",
+            7.0 * PI,
+        );
+        assert_bounds(
+            "loft() { square(4, center=true); translate(z=5) circle(1); translate(z=10) circle(2); }",
+            [-2.0, -2.0, 0.0, 2.0, 2.0, 10.0],
+        );
+        assert_solid(
+            "sweep(path=[[0, 0], [10, 10]]) { square(2, center=true); anti() circle(0.5); }",
+        );
+        assert_solid(
+            "loft() { square(4, center=true); translate(z=5) circle(1); translate(z=10) circle(2); }",
+        );
+        assert_solid("loft(ruled=true) { circle(1); translate(z=2) square(); }");
+
+        assert!(eval("sweep() circle();").is_err());
+        assert!(eval("sweep([[0, 0, 0]]) circle();").is_err());
+        assert!(eval("sweep([[0, 0, 0], [0, 0, 0], [1, 0, 0]]) circle();").is_err());
+        assert!(eval("loft() circle();").is_err());
+        assert!(eval("loft() { circle(); 1; }").is_err());
+        assert!(
+            eval("loft() { circle(2); translate(z=5) { square(2); anti() circle(0.5); } }")
+                .is_err()
+        );
+        assert!(eval(
+            "loft() { circle(2); translate(z=5) { circle(); translate(x=5) circle(); } }"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_fillet_chamfer() {
+        assert_solid("fillet(1) cube(10); fillet(r=0.5, edges=\"z\") cube(5);");
+        assert_solid("chamfer(0.5, edges=\"top\") { cube(5); translate(x=5) cube(5); }");
+
+        // rounding an edge of length l removes a (1 - pi/4) r^2 l sliver, chamfering r^2 l / 2
+        assert_volume(
+            "fillet(1, edges=\"z\") cube([10, 10, 10]);",
+            1000.0 - 40.0 * (1.0 - PI / 4.0),
+        );
+        assert_volume(
+            "fillet(1) cube([10, 10, 10]);",
+            512.0 + 384.0 + 24.0 * PI + 4.0 * PI / 3.0,
+        );
+        assert_volume("chamfer(1, edges=\"z\") cube([10, 10, 10]);", 980.0);
+        assert_bounds(
+            "fillet(1) cube([10, 10, 10]);",
+            [0.0, 0.0, 0.0, 10.0, 10.0, 10.0],
+        );
+
+        assert!(eval("fillet(0) cube(10);").is_err());
+        assert!(eval("chamfer(-1) cube(10);").is_err());
+        assert!(eval("fillet(1, edges=\"bottom\") cube(10);").is_err());
+        assert!(eval("chamfer(1, edges=1) cube(10);").is_err());
+    }
+
+    #[test]
+    fn test_shell_offset() {
+        assert_solid(
+            "shell(1) cube(10); shell(thickness=0.5, open_faces=\"top\") cylinder(r=5, h=5);",
+        );
+        assert_solid("shell(1, open_faces=[\"top\", \"bottom\"]) cube(10);");
+        assert_solid("offset(2) cube(10); offset(distance=-1) sphere(5); offset(0) cube();");
+
+        assert_volume("shell(1) cube([10, 10, 10]);", 1000.0 - 8.0 * 8.0 * 8.0);
+        assert_bounds(
+            "shell(1) cube([10, 10, 10]);",
+            [0.0, 0.0, 0.0, 10.0, 10.0, 10.0],
+        );
+        assert_volume(
+            "shell(1, open_faces=\"top\") cube([10, 10, 10]);",
+            1000.0 - 8.0 * 8.0 * 9.0,
+        );
+        assert_volume(
+            "shell(1, open_faces=[\"top\", \"bottom\"]) cube([10, 10, 10]);",
+            1000.0 - 8.0 * 8.0 * 10.0,
+        );
+        assert_volume(
+            "shell(thickness=0.5, open_faces=\"top\") cylinder(r=5, h=5);",
+            PI * (25.0 * 5.0 - 4.5 * 4.5 * 4.5),
+        );
+
+        // the faces move out flat, the edges become quarter cylinders and the corners eighth spheres
+        let grown =
+            1000.0 + 6.0 * 100.0 * 2.0 + 12.0 * PI * 4.0 / 4.0 * 10.0 + 4.0 / 3.0 * PI * 8.0;
+        assert_volume("offset(2) cube([10, 10, 10]);", grown);
+        assert_bounds(
+            "offset(2) cube([10, 10, 10]);",
+            [-2.0, -2.0, -2.0, 12.0, 12.0, 12.0],
+        );
+        assert_volume("offset(distance=-1) sphere(5);", 4.0 / 3.0 * PI * 64.0);
+        assert_volume("offset(0) cube();", 1.0);
+
+        assert!(eval("shell(0) cube(10);").is_err());
+        assert!(eval("shell(1, open_faces=\"side\") cube(10);").is_err());
+        assert!(eval("shell(1, open_faces=1) cube(10);").is_err());
+        assert!(eval("offset() cube(10);").is_err());
+    }
+}
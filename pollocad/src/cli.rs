@@ -0,0 +1,171 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::diagnostics::Diagnostic;
+use crate::disk_cache::DiskCache;
+use crate::export::{self, ExportOptions};
+use crate::parser;
+use crate::runtime::{Runtime, Value};
+
+const USAGE: &str = "usage: pollocad render <input> -o <output.stl|.step|.brep> [--ascii] \
+[--tolerance <mm>] [--no-cache] [--clear-cache] [--parallel-booleans]";
+
+struct RenderArgs {
+    input: PathBuf,
+    output: PathBuf,
+    options: ExportOptions,
+    no_cache: bool,
+    clear_cache: bool,
+    parallel_booleans: bool,
+}
+
+fn parse_render_args(args: impl Iterator<Item = String>) -> Result<RenderArgs, String> {
+    let mut input = None;
+    let mut output = None;
+    let mut options = ExportOptions::default();
+    let mut no_cache = false;
+    let mut clear_cache = false;
+    let mut parallel_booleans = false;
+
+    let mut args = args.peekable();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => {
+                output = Some(args.next().ok_or("-o requires a file name")?.into());
+            }
+            "--ascii" => options.ascii = true,
+            "--no-cache" => no_cache = true,
+            "--clear-cache" => clear_cache = true,
+            "--parallel-booleans" => parallel_booleans = true,
+            "--tolerance" => {
+                options.tolerance = args
+                    .next()
+                    .and_then(|t| t.parse().ok())
+                    .filter(|t: &f64| *t > 0.0)
+                    .ok_or("--tolerance requires a positive number")?;
+            }
+            a if a.starts_with('-') => return Err(format!("unknown option: {}", a)),
+            _ if input.is_none() => input = Some(arg.into()),
+            _ => return Err(format!("unexpected argument: {}", arg)),
+        }
+    }
+
+    Ok(RenderArgs {
+        input: input.ok_or("no input file given")?,
+        output: output.ok_or("no output file given")?,
+        options,
+        no_cache,
+        clear_cache,
+        parallel_booleans,
+    })
+}
+
+fn render(args: &RenderArgs) -> Result<(), String> {
+    let source = std::fs::read_to_string(&args.input)
+        .map_err(|e| format!("{}: {}", args.input.display(), e))?;
+
+    let body = match parser::parse_source(&source) {
+        Ok((_, body)) => body,
+        Err(e) => {
+            let diag = Diagnostic::from_parse_error(&source, &e);
+            return Err(format!("{}:{}", args.input.display(), diag));
+        }
+    };
+
+    let disk_cache = DiskCache::default_dir().map(DiskCache::new);
+
+    if args.clear_cache {
+        if let Some(cache) = &disk_cache {
+            cache
+                .clear()
+                .map_err(|e| format!("{}: {}", cache.dir().display(), e))?;
+        }
+    }
+
+    let runtime = Arc::new(Runtime::with_disk_cache(
+        disk_cache.filter(|_| !args.no_cache),
+    ));
+    runtime.set_parallel_booleans(args.parallel_booleans);
+
+    let result = runtime.exec(body.as_ref());
+
+    for line in runtime.take_log() {
+        eprintln!("{}", line);
+    }
+
+    let shape = match result {
+        Ok(Value::Solid(geo)) => geo.get_single_shape(),
+        Ok(_) => None,
+        Err(e) => {
+            let diag = Diagnostic::from_exec_error(&source, &e);
+            return Err(format!("{}:{}", args.input.display(), diag));
+        }
+    }
+    .ok_or_else(|| format!("{}: script produced no geometry", args.input.display()))?;
+
+    export::export(&shape, &args.output, &args.options)
+}
+
+/// Runs the command line interface if a subcommand was given, otherwise returns `None` to start the
+/// GUI.
+pub fn run() -> Option<i32> {
+    let mut args = std::env::args().skip(1);
+
+    match args.next().as_deref() {
+        None => None,
+        Some("render") => Some(match parse_render_args(args) {
+            Ok(args) => match render(&args) {
+                Ok(()) => 0,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    1
+                }
+            },
+            Err(e) => {
+                eprintln!("{}\n{}", e, USAGE);
+                2
+            }
+        }),
+        Some(cmd) => {
+            eprintln!("unknown command: {}\n{}", cmd, USAGE);
+            Some(2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<RenderArgs, String> {
+        parse_render_args(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn test_parse_render_args() {
+        let args = parse(&["in.pc", "-o", "out.stl"]).unwrap();
+        assert_eq!(args.input, PathBuf::from("in.pc"));
+        assert_eq!(args.output, PathBuf::from("out.stl"));
+        assert!(!args.parallel_booleans && !args.no_cache && !args.clear_cache);
+
+        let args = parse(&[
+            "--parallel-booleans",
+            "in.pc",
+            "--output",
+            "out.stl",
+            "--no-cache",
+        ])
+        .unwrap();
+        assert!(args.parallel_booleans && args.no_cache);
+
+        let args = parse(&["in.pc", "-o", "out.stl", "--ascii", "--tolerance", "0.5"]).unwrap();
+        assert!(args.options.ascii);
+        assert_eq!(args.options.tolerance, 0.5);
+
+        assert!(parse(&["in.pc"]).is_err());
+        assert!(parse(&["in.pc", "-o"]).is_err());
+        assert!(parse(&["in.pc", "-o", "out.stl", "--tolerance", "0"]).is_err());
+        assert!(parse(&["in.pc", "-o", "out.stl", "--parallel"]).is_err());
+        assert!(parse(&["a.pc", "b.pc", "-o", "out.stl"]).is_err());
+    }
+}
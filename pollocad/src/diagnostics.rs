@@ -0,0 +1,106 @@
+use std::fmt;
+use std::ops::Range;
+
+use crate::parser::ErrorDetail;
+use crate::runtime;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        })
+    }
+}
+
+/// A message tied to a byte range in the source code. Lines and columns are 1-based, with columns
+/// counted in characters.
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub range: Range<usize>,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+    pub severity: Severity,
+}
+
+impl Diagnostic {
+    pub fn new(
+        source: &str,
+        range: Range<usize>,
+        message: impl Into<String>,
+        severity: Severity,
+    ) -> Diagnostic {
+        let start = range.start.min(source.len());
+        let before = &source[..start];
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+
+        Diagnostic {
+            range,
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            message: message.into(),
+            severity,
+        }
+    }
+
+    pub fn from_parse_error(source: &str, error: &nom::Err<ErrorDetail>) -> Diagnostic {
+        match error {
+            nom::Err::Error(e) | nom::Err::Failure(e) => Diagnostic::new(
+                source,
+                e.offset()..e.offset(),
+                format!("Syntax error: {}", e.message()),
+                Severity::Error,
+            ),
+            nom::Err::Incomplete(_) => Diagnostic::new(
+                source,
+                source.len()..source.len(),
+                "Unexpected end of input",
+                Severity::Error,
+            ),
+        }
+    }
+
+    pub fn from_exec_error(source: &str, error: &runtime::Error) -> Diagnostic {
+        Diagnostic::new(source, error.pos(), error.reason(), Severity::Error)
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}: {}",
+            self.line, self.column, self.severity, self.message
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_line_column() {
+        let source = "cube(1);\nfoo(2);\n  ä bar";
+
+        let d = Diagnostic::new(source, 0..4, "", Severity::Error);
+        assert_eq!((d.line, d.column), (1, 1));
+
+        let d = Diagnostic::new(source, 9..12, "", Severity::Error);
+        assert_eq!((d.line, d.column), (2, 1));
+
+        let d = Diagnostic::new(source, 22..25, "msg", Severity::Warning);
+        assert_eq!((d.line, d.column), (3, 5));
+        assert_eq!(d.to_string(), "3:5: warning: msg");
+
+        let d = Diagnostic::new(source, 100..100, "", Severity::Error);
+        assert_eq!((d.line, d.column), (3, 8));
+    }
+}
@@ -0,0 +1,266 @@
+use std::hash::Hasher;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use pollocad_occt::Shape;
+use sha2::{Digest, Sha256};
+
+/// The default limit for the total size of the cached files.
+const DEFAULT_MAX_SIZE: u64 = 1 << 30;
+
+/// Identifies a cache entry by the SHA-256 digest of everything hashed into it. Unlike with a
+/// 64-bit hash, two different calls will practically never share an entry.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Key([u8; 32]);
+
+impl Key {
+    /// Returns a 64-bit hash of the key, for identifying the cached shape in memory.
+    pub fn hash64(&self) -> u64 {
+        u64::from_le_bytes(self.0[..8].try_into().unwrap())
+    }
+}
+
+/// Builds a `Key` from values that implement `Hash`.
+#[derive(Default)]
+pub struct KeyHasher(Sha256);
+
+impl KeyHasher {
+    pub fn key(&self) -> Key {
+        Key(self.0.clone().finalize().into())
+    }
+}
+
+impl Hasher for KeyHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        self.key().hash64()
+    }
+}
+
+/// Stores shapes as BREP files in a directory, so that expensive results survive restarts. Once
+/// the files grow past a maximum total size, the least recently used ones are removed.
+pub struct DiskCache {
+    dir: PathBuf,
+    max_size: u64,
+    /// Estimated total size of the files, or `None` until the directory is next scanned. Other
+    /// processes may write to the directory as well, so a scan before evicting corrects it.
+    size: Mutex<Option<u64>>,
+}
+
+impl DiskCache {
+    pub fn new(dir: impl Into<PathBuf>) -> DiskCache {
+        DiskCache {
+            dir: dir.into(),
+            max_size: DEFAULT_MAX_SIZE,
+            size: Mutex::new(None),
+        }
+    }
+
+    /// Limits the total size of the cached files to `max_size` bytes.
+    pub fn with_max_size(self, max_size: u64) -> DiskCache {
+        DiskCache { max_size, ..self }
+    }
+
+    /// The platform cache directory, usually `~/.cache/pollocad`.
+    pub fn default_dir() -> Option<PathBuf> {
+        let env_dir = |name| {
+            std::env::var_os(name)
+                .filter(|d| !d.is_empty())
+                .map(PathBuf::from)
+        };
+
+        env_dir("XDG_CACHE_HOME")
+            .or_else(|| env_dir("HOME").map(|h| h.join(".cache")))
+            .or_else(|| env_dir("LOCALAPPDATA"))
+            .map(|d| d.join("pollocad"))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Entries are named by the full digest of their key, so a file is only ever read back for
+    /// the exact key it was written for.
+    fn path(&self, key: Key) -> PathBuf {
+        let name: String = key.0.iter().map(|b| format!("{:02x}", b)).collect();
+        self.dir.join(name + ".brep")
+    }
+
+    /// Returns the cached shape for `key`. A missing entry is `Ok(None)`.
+    pub fn get(&self, key: Key) -> Result<Option<Shape>, String> {
+        let path = self.path(key);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        // the modification time doubles as the time of last use for eviction
+        let _ = std::fs::File::options()
+            .append(true)
+            .open(&path)
+            .and_then(|f| f.set_modified(SystemTime::now()));
+
+        Shape::read_brep(&path)
+            .map(Some)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn put(&self, key: Key, shape: &Shape) -> Result<(), String> {
+        static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        std::fs::create_dir_all(&self.dir).map_err(|e| format!("{}: {}", self.dir.display(), e))?;
+
+        // write to a temporary file first so that other readers never see a partial file
+        let path = self.path(key);
+        let tmp = path.with_extension(format!(
+            "{}-{}.tmp",
+            std::process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        shape.write_brep(&tmp).map_err(|e| e.to_string())?;
+        std::fs::rename(&tmp, &path).map_err(|e| {
+            let _ = std::fs::remove_file(&tmp);
+            format!("{}: {}", path.display(), e)
+        })?;
+
+        // only scan the directory when the entries may have outgrown the maximum size
+        let len = std::fs::metadata(&path).map_or(0, |m| m.len());
+        let mut size = self.size.lock().unwrap();
+        *size = match *size {
+            Some(size) if size + len <= self.max_size => Some(size + len),
+            _ => None,
+        };
+        if size.is_none() {
+            *size = Some(
+                self.evict()
+                    .map_err(|e| format!("{}: {}", self.dir.display(), e))?,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Removes the least recently used entries until the rest fit in the maximum size, and
+    /// returns the total size of the rest.
+    fn evict(&self) -> io::Result<u64> {
+        let mut entries = Vec::new();
+        let mut total = 0;
+
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension() != Some("brep".as_ref()) {
+                continue;
+            }
+
+            // another process may have removed the entry in the meantime
+            let Ok(meta) = std::fs::metadata(&path) else {
+                continue;
+            };
+            total += meta.len();
+            entries.push((meta.modified()?, meta.len(), path));
+        }
+
+        entries.sort();
+
+        for (_, len, path) in entries {
+            if total <= self.max_size {
+                break;
+            }
+
+            match std::fs::remove_file(&path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => total -= len,
+            }
+        }
+
+        Ok(total)
+    }
+
+    /// Removes every cached shape.
+    pub fn clear(&self) -> io::Result<()> {
+        *self.size.lock().unwrap() = None;
+
+        match std::fs::remove_dir_all(&self.dir) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            r => r,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::hash::Hash;
+
+    fn key(n: u32) -> Key {
+        let mut h = KeyHasher::default();
+        n.hash(&mut h);
+        h.key()
+    }
+
+    #[test]
+    fn test_put_get_clear() {
+        let dir = std::env::temp_dir().join(format!("pollocad-cache-test-{}", std::process::id()));
+        let cache = DiskCache::new(&dir);
+
+        assert!(matches!(cache.get(key(1)), Ok(None)));
+
+        let shape = Shape::new_cube(1.0, 2.0, 3.0).unwrap();
+        cache.put(key(1), &shape).unwrap();
+        assert!(matches!(cache.get(key(1)), Ok(Some(_))));
+        assert!(matches!(cache.get(key(2)), Ok(None)));
+
+        cache.clear().unwrap();
+        assert!(!dir.exists());
+        assert!(matches!(cache.get(key(1)), Ok(None)));
+        cache.clear().unwrap();
+    }
+
+    #[test]
+    fn test_keys() {
+        assert_eq!(key(1), key(1));
+        assert_ne!(key(1), key(2));
+
+        // the key covers everything hashed, not just a prefix of it
+        let mut h = KeyHasher::default();
+        (1u32, "a").hash(&mut h);
+        let mut h2 = KeyHasher::default();
+        (1u32, "b").hash(&mut h2);
+        assert_ne!(h.key(), h2.key());
+    }
+
+    #[test]
+    fn test_evict() {
+        let dir = std::env::temp_dir().join(format!("pollocad-evict-test-{}", std::process::id()));
+        let shape = Shape::new_cube(1.0, 2.0, 3.0).unwrap();
+
+        DiskCache::new(&dir).put(key(0), &shape).unwrap();
+        let size = std::fs::metadata(DiskCache::new(&dir).path(key(0)))
+            .unwrap()
+            .len();
+
+        // room for two entries
+        let cache = DiskCache::new(&dir).with_max_size(size * 5 / 2);
+        let tick = || std::thread::sleep(std::time::Duration::from_millis(20));
+        tick();
+        cache.put(key(1), &shape).unwrap();
+        assert_eq!(*cache.size.lock().unwrap(), Some(size * 2));
+        tick();
+        assert!(matches!(cache.get(key(0)), Ok(Some(_))));
+        tick();
+        cache.put(key(2), &shape).unwrap();
+
+        // the entry used longest ago goes first
+        assert!(matches!(cache.get(key(1)), Ok(None)));
+        assert!(matches!(cache.get(key(0)), Ok(Some(_))));
+        assert!(matches!(cache.get(key(2)), Ok(Some(_))));
+
+        cache.clear().unwrap();
+    }
+}
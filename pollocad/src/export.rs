@@ -0,0 +1,53 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use pollocad_occt::Shape;
+
+use crate::stl;
+
+#[derive(Clone, Copy, Debug)]
+pub struct ExportOptions {
+    pub ascii: bool,
+    pub tolerance: f64,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        ExportOptions {
+            ascii: false,
+            tolerance: 0.01,
+        }
+    }
+}
+
+/// Writes the shape to a file, choosing the format based on the file extension.
+pub fn export(shape: &Shape, path: &Path, options: &ExportOptions) -> Result<(), String> {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+
+    match ext.as_deref() {
+        Some("stl") => {
+            let mesh = shape.mesh(options.tolerance).map_err(|e| e.to_string())?;
+
+            let mut out = BufWriter::new(
+                File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?,
+            );
+
+            if options.ascii {
+                stl::write_ascii(&mut out, &mesh)
+            } else {
+                stl::write_binary(&mut out, &mesh)
+            }
+            .map_err(|e| format!("{}: {}", path.display(), e))
+        }
+        Some("step" | "stp") => shape.write_step(path).map_err(|e| e.to_string()),
+        Some("brep") => shape.write_brep(path).map_err(|e| e.to_string()),
+        _ => Err(format!(
+            "{}: unknown export format (expected .stl, .step, .stp or .brep)",
+            path.display()
+        )),
+    }
+}
@@ -1,15 +1,47 @@
 use cgmath::SquareMatrix as _;
 use pollocad_occt::{Shape, BooleanOp};
 use std::borrow::Cow;
+use std::hash::{Hash, Hasher};
 use std::sync::Arc;
 
-pub use pollocad_occt::Error;
+pub use pollocad_occt::{EdgeSelector, Error};
 
 #[derive(Clone)]
 struct SolidItem {
     xform: Option<cgmath::Matrix4<f64>>,
     shape: Arc<Shape>,
+    /// Identifies the shape by how it was constructed, so that equal shapes from different
+    /// evaluations hash the same.
+    shape_hash: u64,
     anti: bool,
+    /// Which primitive the shape is, if any, which lets Minkowski sums with it be computed
+    /// exactly.
+    primitive: Option<Primitive>,
+}
+
+/// A primitive standing on the item's origin, or centered on it in the case of the sphere.
+#[derive(Clone, Copy)]
+enum Primitive {
+    Sphere(f64),
+    Cylinder(f64, f64),
+}
+
+impl Hash for SolidItem {
+    fn hash<H: Hasher>(&self, h: &mut H) {
+        self.shape_hash.hash(h);
+        self.anti.hash(h);
+        if let Some(xform) = &self.xform {
+            let m: &[f64; 16] = xform.as_ref();
+            m.map(f64::to_bits).hash(h);
+        }
+    }
+}
+
+/// Like hashing, compares how the shapes were constructed rather than the shapes themselves.
+impl PartialEq for SolidItem {
+    fn eq(&self, other: &SolidItem) -> bool {
+        self.shape_hash == other.shape_hash && self.anti == other.anti && self.xform == other.xform
+    }
 }
 
 impl SolidItem {
@@ -21,15 +53,112 @@ impl SolidItem {
     }
 }
 
+/// Why a Minkowski sum could not be computed.
+#[derive(Debug)]
+pub enum MinkowskiError {
+    Occt(Error),
+    /// The sum is flat, so it is not a solid.
+    Flat,
+    /// An operand is not convex and the sum can't be computed exactly.
+    NonConvex,
+}
+
+impl From<Error> for MinkowskiError {
+    fn from(e: Error) -> Self {
+        MinkowskiError::Occt(e)
+    }
+}
+
+impl std::fmt::Display for MinkowskiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MinkowskiError::Occt(e) => e.fmt(f),
+            MinkowskiError::Flat => f.write_str("minkowski of flat shapes is not supported"),
+            MinkowskiError::NonConvex => f.write_str(
+                "minkowski of a non-convex shape is only supported with a sphere, or for a 2D \
+                 shape with an upright cylinder",
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MinkowskiError {}
+
+#[derive(Hash, PartialEq)]
 pub struct Solid(Vec<SolidItem>);
 
 impl Solid {
+    fn from_shape(shape: Shape, shape_hash: u64) -> Solid {
+        Solid(vec![SolidItem {
+            xform: None,
+            shape: Arc::new(shape),
+            shape_hash,
+            anti: false,
+            primitive: None,
+        }])
+    }
+
     pub fn new_cube(x: f64, y: f64, z: f64) -> Result<Solid, Error> {
-        Ok(Shape::new_cube(x, y, z)?.into())
+        Ok(Solid::from_shape(
+            Shape::new_cube(x, y, z)?,
+            fxhash::hash64(&("cube", [x, y, z].map(f64::to_bits))),
+        ))
     }
 
     pub fn new_cylinder(r: f64, h: f64) -> Result<Solid, Error> {
-        Ok(Shape::new_cylinder(r, h)?.into())
+        let mut solid = Solid::from_shape(
+            Shape::new_cylinder(r, h)?,
+            fxhash::hash64(&("cylinder", [r, h].map(f64::to_bits))),
+        );
+        solid.0[0].primitive = Some(Primitive::Cylinder(r, h));
+
+        Ok(solid)
+    }
+
+    pub fn new_sphere(r: f64) -> Result<Solid, Error> {
+        let mut solid = Solid::from_shape(
+            Shape::new_sphere(r)?,
+            fxhash::hash64(&("sphere", r.to_bits())),
+        );
+        solid.0[0].primitive = Some(Primitive::Sphere(r));
+
+        Ok(solid)
+    }
+
+    pub fn new_cone(r1: f64, r2: f64, h: f64) -> Result<Solid, Error> {
+        Ok(Solid::from_shape(
+            Shape::new_cone(r1, r2, h)?,
+            fxhash::hash64(&("cone", [r1, r2, h].map(f64::to_bits))),
+        ))
+    }
+
+    pub fn new_torus(r1: f64, r2: f64) -> Result<Solid, Error> {
+        Ok(Solid::from_shape(
+            Shape::new_torus(r1, r2)?,
+            fxhash::hash64(&("torus", [r1, r2].map(f64::to_bits))),
+        ))
+    }
+
+    pub fn new_wedge(x: f64, y: f64, z: f64, ltx: f64) -> Result<Solid, Error> {
+        Ok(Solid::from_shape(
+            Shape::new_wedge(x, y, z, ltx)?,
+            fxhash::hash64(&("wedge", [x, y, z, ltx].map(f64::to_bits))),
+        ))
+    }
+
+    pub fn new_polygon(points: &[[f64; 2]]) -> Result<Solid, Error> {
+        let bits: Vec<_> = points.iter().map(|p| p.map(f64::to_bits)).collect();
+        Ok(Solid::from_shape(
+            Shape::new_polygon(points)?,
+            fxhash::hash64(&("polygon", bits)),
+        ))
+    }
+
+    pub fn new_circle(r: f64) -> Result<Solid, Error> {
+        Ok(Solid::from_shape(
+            Shape::new_circle(r)?,
+            fxhash::hash64(&("circle", r.to_bits())),
+        ))
     }
 
     pub fn anti(&self) -> Solid {
@@ -37,9 +166,8 @@ impl Solid {
             self.0
                 .iter()
                 .map(|i| SolidItem {
-                    xform: i.xform,
-                    shape: i.shape.clone(),
                     anti: !i.anti,
+                    ..i.clone()
                 })
                 .collect(),
         )
@@ -51,77 +179,379 @@ impl Solid {
                 .iter()
                 .map(|i| SolidItem {
                     xform: Some(mat * i.xform.unwrap_or_else(|| cgmath::Matrix4::identity())),
-                    shape: i.shape.clone(),
-                    anti: i.anti,
+                    ..i.clone()
                 })
                 .collect(),
         )
     }
 
+    /// Unionizes the solid and applies `f` to the resulting shape. `op` identifies the operation
+    /// and its parameters for the hash of the result.
+    fn map_unionized(
+        &self,
+        op: impl Hash,
+        f: impl FnOnce(&Shape) -> Result<Shape, Error>,
+    ) -> Result<Solid, Error> {
+        let flat = self.unionize()?;
+        let Some(item) = flat.0.first() else { return Ok(flat) };
+
+        Ok(Solid::from_shape(
+            f(&item.shape)?,
+            fxhash::hash64(&(op, item.shape_hash)),
+        ))
+    }
+
+    /// Extrudes the 2D shapes in this solid upwards from the XY plane.
+    pub fn linear_extrude(&self, height: f64, twist: f64, scale: [f64; 2]) -> Result<Solid, Error> {
+        let params = [height, twist, scale[0], scale[1]].map(f64::to_bits);
+        self.map_unionized(("linear_extrude", params), |s| {
+            s.linear_extrude(height, twist, scale)
+        })
+    }
+
+    /// Revolves the 2D shapes in this solid around the Z axis, their X axis being the radius.
+    pub fn rotate_extrude(&self, angle: f64) -> Result<Solid, Error> {
+        self.map_unionized(("rotate_extrude", angle.to_bits()), |s| s.revolve(angle))
+    }
+
+    /// Sweeps the 2D shapes in this solid along the polyline through `path`.
+    pub fn sweep(&self, path: &[[f64; 3]]) -> Result<Solid, Error> {
+        let bits: Vec<_> = path.iter().map(|p| p.map(f64::to_bits)).collect();
+        self.map_unionized(("sweep", bits), |s| s.sweep(path))
+    }
+
+    /// Lofts a solid through the 2D shapes of each of the `sections`, which may be placed anywhere
+    /// in space.
+    pub fn loft<'a>(
+        sections: impl Iterator<Item = &'a Solid>,
+        ruled: bool,
+    ) -> Result<Solid, Error> {
+        let flat = sections
+            .map(|s| s.unionize())
+            .collect::<Result<Vec<_>, _>>()?;
+        let items: Vec<_> = flat.iter().filter_map(|s| s.0.first()).collect();
+
+        let shapes: Vec<&Shape> = items.iter().map(|i| i.shape.as_ref()).collect();
+        let hashes: Vec<_> = items.iter().map(|i| i.shape_hash).collect();
+
+        Ok(Solid::from_shape(
+            Shape::loft(&shapes, ruled)?,
+            fxhash::hash64(&("loft", ruled, hashes)),
+        ))
+    }
+
+    /// Returns the points of a tessellation of the solid. The tessellation may deviate from the
+    /// surface by a fixed fraction of the size of each shape, so that tiny and huge shapes are
+    /// faceted alike.
+    fn tessellation_points(&self) -> Result<Vec<[f64; 3]>, Error> {
+        const RELATIVE_DEFLECTION: f64 = 1e-3;
+
+        let mut points = Vec::new();
+        for item in self.unionize()?.0 {
+            let b = item.shape.bounds();
+            let diagonal = (b.xmax - b.xmin)
+                .hypot(b.ymax - b.ymin)
+                .hypot(b.zmax - b.zmin);
+            if diagonal > 0.0 {
+                points.extend(item.shape.mesh(diagonal * RELATIVE_DEFLECTION)?.vertices);
+            }
+        }
+
+        Ok(points)
+    }
+
+    /// Builds the convex hull of `points` as a solid, or returns `None` if they are flat.
+    fn from_hull_points(points: &[[f64; 3]], hash: u64) -> Result<Option<Solid>, Error> {
+        if points.is_empty() {
+            return Ok(Some(Solid(vec![])));
+        }
+
+        let Some(triangles) = crate::hull::convex_hull(points) else { return Ok(None) };
+
+        let triangles: Vec<_> = triangles.iter().map(|t| t.map(|i| i as u32)).collect();
+        Ok(Some(Solid::from_shape(
+            Shape::from_triangles(points, &triangles)?,
+            hash,
+        )))
+    }
+
+    /// Computes the convex hull of all of the `solids`. The hull is built from the vertices of a
+    /// tessellation of the shapes, so curved surfaces come out faceted. Returns `None` if the
+    /// shapes are flat, as their hull would not be a solid.
+    pub fn hull<'a>(solids: impl Iterator<Item = &'a Solid>) -> Result<Option<Solid>, Error> {
+        let mut points = Vec::new();
+        let mut hashes = Vec::new();
+        for solid in solids {
+            points.extend(solid.tessellation_points()?);
+            hashes.push(fxhash::hash64(solid));
+        }
+
+        Solid::from_hull_points(&points, fxhash::hash64(&("hull", hashes)))
+    }
+
+    /// Returns the primitive and its position if the solid is a single primitive that has at most
+    /// been moved.
+    fn as_primitive(&self) -> Option<(Primitive, cgmath::Vector3<f64>)> {
+        let [item] = self.0.as_slice() else { return None };
+        let primitive = item.primitive.filter(|_| !item.anti)?;
+
+        match item.xform {
+            None => Some((primitive, cgmath::Vector3::new(0.0, 0.0, 0.0))),
+            Some(m)
+                if m.x.truncate() == cgmath::Vector3::unit_x()
+                    && m.y.truncate() == cgmath::Vector3::unit_y()
+                    && m.z.truncate() == cgmath::Vector3::unit_z() =>
+            {
+                Some((primitive, m.w.truncate()))
+            }
+            Some(_) => None,
+        }
+    }
+
+    /// Computes the Minkowski sum of two solids. The sum of a solid with a sphere is exact,
+    /// computed by offsetting the solid, and so is the sum of a 2D shape in the XY plane with an
+    /// upright cylinder, computed as a prism of the grown outline. Otherwise the sum is the hull of
+    /// the pairwise sums of the operands' tessellation vertices, which is only right for convex
+    /// operands, so non-convex ones are rejected.
+    pub fn minkowski(&self, other: &Solid) -> Result<Solid, MinkowskiError> {
+        for (a, b) in [(self, other), (other, self)] {
+            let Some((primitive, offset)) = b.as_primitive() else { continue };
+
+            let flat = a.unionize()?;
+            let planar = flat.0.first().filter(|item| item.shape.is_planar_xy());
+
+            let sum = match (primitive, planar) {
+                // offsetting a 2D shape would grow the face rather than round it into a slab
+                (Primitive::Sphere(_), Some(_)) => continue,
+                (Primitive::Sphere(r), None) => a.offset(r)?,
+                (Primitive::Cylinder(r, h), Some(item)) => {
+                    let params = [r, h].map(f64::to_bits);
+                    Solid::from_shape(
+                        item.shape.offset_2d(r)?.linear_extrude(h, 0.0, [1.0, 1.0])?,
+                        fxhash::hash64(&("minkowski", params, item.shape_hash)),
+                    )
+                }
+                (Primitive::Cylinder(..), None) => continue,
+            };
+
+            return Ok(sum.transform(&cgmath::Matrix4::from_translation(offset)));
+        }
+
+        for solid in [self, other] {
+            if !solid.is_convex()? {
+                return Err(MinkowskiError::NonConvex);
+            }
+        }
+
+        // only the vertices of the hulls can contribute to the hull of the sums
+        let hull_vertices = |solid: &Solid| -> Result<Vec<[f64; 3]>, Error> {
+            let points = solid.tessellation_points()?;
+            let Some(triangles) = crate::hull::convex_hull(&points) else { return Ok(points) };
+
+            let mut indices: Vec<_> = triangles.into_iter().flatten().collect();
+            indices.sort_unstable();
+            indices.dedup();
+
+            Ok(indices.into_iter().map(|i| points[i]).collect())
+        };
+
+        let a = hull_vertices(self)?;
+        let b = hull_vertices(other)?;
+        if a.is_empty() || b.is_empty() {
+            return Ok(Solid(vec![]));
+        }
+
+        let sums: Vec<_> = a
+            .iter()
+            .flat_map(|p| {
+                b.iter()
+                    .map(move |q| [p[0] + q[0], p[1] + q[1], p[2] + q[2]])
+            })
+            .collect();
+
+        Solid::from_hull_points(&sums, fxhash::hash64(&("minkowski", self, other)))?
+            .ok_or(MinkowskiError::Flat)
+    }
+
+    /// Returns whether the solid is convex, judged by comparing its volume with that of its hull.
+    /// 2D shapes in the XY plane are extruded, so that their areas are compared instead.
+    fn is_convex(&self) -> Result<bool, Error> {
+        // tessellating curved surfaces shrinks the hull a little, but a notch grows it
+        const TOLERANCE: f64 = 1e-3;
+
+        let flat = self.unionize()?;
+        let Some(item) = flat.0.first() else { return Ok(true) };
+
+        let shape = if item.shape.is_planar_xy() {
+            item.shape.linear_extrude(1.0, 0.0, [1.0, 1.0])?
+        } else {
+            (*item.shape).clone()
+        };
+        let volume = shape.volume();
+
+        let points = Solid::from_shape(shape, 0).tessellation_points()?;
+        let hull = Solid::from_hull_points(&points, 0)?.and_then(|h| h.get_single_shape());
+
+        // a shape that is flat in some other plane has no hull and can't be checked
+        Ok(hull.is_none_or(|h| h.volume() <= volume * (1.0 + TOLERANCE)))
+    }
+
+    pub fn fillet(&self, r: f64, edges: EdgeSelector) -> Result<Solid, Error> {
+        self.map_unionized(("fillet", r.to_bits(), edges as u32), |s| {
+            s.fillet(r, edges)
+        })
+    }
+
+    pub fn chamfer(&self, d: f64, edges: EdgeSelector) -> Result<Solid, Error> {
+        self.map_unionized(("chamfer", d.to_bits(), edges as u32), |s| {
+            s.chamfer(d, edges)
+        })
+    }
+
+    pub fn shell(&self, thickness: f64, open_top: bool, open_bottom: bool) -> Result<Solid, Error> {
+        let params = (thickness.to_bits(), open_top, open_bottom);
+        self.map_unionized(("shell", params), |s| {
+            s.shell(thickness, open_top, open_bottom)
+        })
+    }
+
+    pub fn offset(&self, distance: f64) -> Result<Solid, Error> {
+        self.map_unionized(("offset", distance.to_bits()), |s| s.offset(distance))
+    }
+
     pub fn unionize(&self) -> Result<Solid, Error> {
+        self.unionize_with(false)
+    }
+
+    /// Like `unionize`, but with `parallel`, lets OCCT run the booleans on its own worker threads.
+    pub fn unionize_with(&self, parallel: bool) -> Result<Solid, Error> {
         let (anti, real): (Vec<_>, Vec<_>) = self.0.iter().partition(|i| i.anti);
 
-        let Some(first) = real.first() else {
+        let real = real
+            .iter()
+            .map(|i| i.xformed_shape())
+            .collect::<Result<Vec<_>, Error>>()?;
+        let anti = anti
+            .iter()
+            .map(|i| i.xformed_shape())
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let Some((first, rest)) = real.split_first() else {
             return Ok(Solid(vec![]));
         };
 
-        let mut acc = first.xformed_shape()?.into_owned();
-        /*if let Some(x) = &first.xform {
-            acc.transform(x.as_ref())?;
-        }*/
-
-        for item in real.iter().skip(1) {
-            acc = acc.boolean_op(item.xformed_shape()?.as_ref(), BooleanOp::Union)?;
-        }
+        let mut acc = if rest.is_empty() {
+            first.as_ref().clone()
+        } else {
+            let rest: Vec<&Shape> = rest.iter().map(|s| s.as_ref()).collect();
+            Shape::boolean_many(&[first.as_ref()], &rest, BooleanOp::Union, parallel)?
+        };
 
-        for item in anti {
-            acc = acc.boolean_op(item.xformed_shape()?.as_ref(), BooleanOp::Difference)?;
+        if !anti.is_empty() {
+            let anti: Vec<&Shape> = anti.iter().map(|s| s.as_ref()).collect();
+            acc = Shape::boolean_many(&[&acc], &anti, BooleanOp::Difference, parallel)?;
         }
 
-        Ok(Solid(vec![SolidItem {
-            xform: None,
-            shape: Arc::new(acc),
-            anti: false,
-        }]))
+        Ok(Solid::from_shape(acc, fxhash::hash64(&("union", self))))
     }
 
-    pub fn intersectionize<'a>(solids: impl Iterator<Item = &'a Solid>) -> Result<Solid, Error> {
+    pub fn intersectionize<'a>(
+        solids: impl Iterator<Item = &'a Solid>,
+        parallel: bool,
+    ) -> Result<Solid, Error> {
+        let mut hasher = fxhash::FxHasher64::default();
+        "intersection".hash(&mut hasher);
+
         let items = solids
-            .map(|s| Ok(s.unionize()?.0[0].shape.clone()))
+            .map(|s| {
+                s.hash(&mut hasher);
+                Ok(s.unionize_with(parallel)?.0.first().map(|i| i.shape.clone()))
+            })
             .collect::<Result<Vec<_>, Error>>()?;
 
-        let Some(first) = items.first() else {
+        // the intersection with an empty child (e.g. one that is all anti) is empty
+        let Some(items) = items.into_iter().collect::<Option<Vec<_>>>() else {
             return Ok(Solid(vec![]));
         };
 
-        let mut acc = (**first).clone();
+        let Some((first, rest)) = items.split_first() else {
+            return Ok(Solid(vec![]));
+        };
 
-        for s in items.iter().skip(1) {
-            acc = acc.boolean_op(s, BooleanOp::Intersection)?;
-        }
+        let acc = if rest.is_empty() {
+            (**first).clone()
+        } else {
+            let rest: Vec<&Shape> = rest.iter().map(|s| s.as_ref()).collect();
+            Shape::boolean_many(&[first.as_ref()], &rest, BooleanOp::Intersection, parallel)?
+        };
 
-        Ok(Solid(vec![SolidItem {
-            xform: None,
-            shape: Arc::new(acc),
-            anti: false,
-        }]))
+        Ok(Solid::from_shape(acc, hasher.finish()))
     }
 
     pub fn combine<'a>(solids: impl Iterator<Item = &'a Solid>) -> Solid {
         Solid(solids.flat_map(|s| s.0.iter().cloned()).collect())
     }
 
+    /// Wraps a shape that is identified by an externally computed hash, such as a cache key.
+    pub fn from_hashed_shape(shape: Arc<Shape>, shape_hash: u64) -> Solid {
+        Solid(vec![SolidItem {
+            xform: None,
+            shape,
+            shape_hash,
+            anti: false,
+            primitive: None,
+        }])
+    }
+
+    /// Whether unionizing the solid takes a boolean operation.
+    pub fn needs_union(&self) -> bool {
+        self.0.len() > 1
+    }
+
+    /// Returns the shape if the solid is exactly one untransformed shape.
+    pub fn as_plain_shape(&self) -> Option<&Arc<Shape>> {
+        match self.0.as_slice() {
+            [item] if item.xform.is_none() && !item.anti => Some(&item.shape),
+            _ => None,
+        }
+    }
+
     pub fn get_single_shape(&self) -> Option<Arc<Shape>> {
         self.0.get(0).map(|n| n.shape.clone())
     }
 }
 
-impl From<Shape> for Solid {
-    fn from(shape: Shape) -> Solid {
-        Solid(vec![SolidItem {
-            xform: None,
-            shape: Arc::new(shape),
-            anti: false,
-        }])
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_minkowski_sphere() {
+        let cube = Solid::new_cube(10.0, 10.0, 10.0).unwrap();
+        let moved = cgmath::Matrix4::from_translation([5.0, 0.0, 0.0].into());
+        let sphere = Solid::new_sphere(1.0).unwrap().transform(&moved);
+
+        // the sum takes the exact path, offsetting the cube and moving it by the sphere's center
+        let expected = fxhash::hash64(&cube.offset(1.0).unwrap().transform(&moved));
+        for sum in [cube.minkowski(&sphere), sphere.minkowski(&cube)] {
+            assert_eq!(fxhash::hash64(&sum.unwrap()), expected);
+        }
+    }
+
+    #[test]
+    fn test_pattern_shares_shapes() {
+        let cube = Solid::new_cube(1.0, 1.0, 1.0).unwrap();
+        let copies: Vec<_> = (0..3)
+            .map(|i| {
+                let step = cgmath::Vector3::new(2.0 * i as f64, 0.0, 0.0);
+                cube.transform(&cgmath::Matrix4::from_translation(step))
+            })
+            .collect();
+
+        let pattern = Solid::combine(copies.iter());
+        assert_eq!(pattern.0.len(), 3);
+        for item in &pattern.0 {
+            assert!(Arc::ptr_eq(&item.shape, &cube.0[0].shape));
+        }
     }
 }
@@ -0,0 +1,297 @@
+use std::collections::{HashMap, VecDeque};
+
+use cgmath::{InnerSpace, Vector3};
+
+struct Face {
+    vertices: [usize; 3],
+    normal: Vector3<f64>,
+    offset: f64,
+    /// Points above this face that have not been added to the hull yet.
+    outside: Vec<usize>,
+    alive: bool,
+}
+
+impl Face {
+    /// Returns `None` if the face has no area, and so no normal.
+    fn new(points: &[Vector3<f64>], vertices: [usize; 3]) -> Option<Face> {
+        let [a, b, c] = vertices.map(|i| points[i]);
+        let cross = (b - a).cross(c - a);
+        if cross.magnitude() <= f64::EPSILON * (b - a).magnitude() * (c - a).magnitude() {
+            return None;
+        }
+        let normal = cross.normalize();
+
+        Some(Face {
+            vertices,
+            normal,
+            offset: normal.dot(a),
+            outside: Vec::new(),
+            alive: true,
+        })
+    }
+
+    fn distance(&self, p: Vector3<f64>) -> f64 {
+        self.normal.dot(p) - self.offset
+    }
+
+    fn edges(&self) -> [(usize, usize); 3] {
+        let [a, b, c] = self.vertices;
+        [(a, b), (b, c), (c, a)]
+    }
+}
+
+struct Hull<'a> {
+    points: &'a [Vector3<f64>],
+    eps: f64,
+    faces: Vec<Face>,
+    /// Maps every directed edge to the face it belongs to, counterclockwise seen from outside.
+    edges: HashMap<(usize, usize), usize>,
+}
+
+impl Hull<'_> {
+    fn add_face(&mut self, vertices: [usize; 3]) -> Option<usize> {
+        let index = self.faces.len();
+        let face = Face::new(self.points, vertices)?;
+
+        for edge in face.edges() {
+            self.edges.insert(edge, index);
+        }
+        self.faces.push(face);
+
+        Some(index)
+    }
+
+    /// Hands each point to the first of `faces` it lies above. Points below all of them are
+    /// inside the hull and dropped.
+    fn assign(&mut self, candidates: impl IntoIterator<Item = usize>, faces: &[usize]) {
+        for p in candidates {
+            let point = self.points[p];
+            if let Some(&f) = faces
+                .iter()
+                .find(|&&f| self.faces[f].distance(point) > self.eps)
+            {
+                self.faces[f].outside.push(p);
+            }
+        }
+    }
+
+    /// Adds `eye`, which lies above face `start`, to the hull. Returns the new faces, or `None` if
+    /// rounding errors have left the hull inconsistent.
+    fn add_point(&mut self, start: usize, eye: usize) -> Option<Vec<usize>> {
+        let point = self.points[eye];
+
+        // find the faces visible from the new point, starting from one known to be visible
+        let mut visible = vec![start];
+        let mut queue = VecDeque::from([start]);
+        self.faces[start].alive = false;
+
+        let mut horizon = Vec::new();
+        while let Some(f) = queue.pop_front() {
+            for (a, b) in self.faces[f].edges() {
+                let neighbour = *self.edges.get(&(b, a))?;
+                if !self.faces[neighbour].alive {
+                    continue;
+                }
+
+                if self.faces[neighbour].distance(point) > self.eps {
+                    self.faces[neighbour].alive = false;
+                    visible.push(neighbour);
+                    queue.push_back(neighbour);
+                } else {
+                    horizon.push((a, b));
+                }
+            }
+        }
+
+        let mut orphans = Vec::new();
+        for &f in &visible {
+            for edge in self.faces[f].edges() {
+                self.edges.remove(&edge);
+            }
+            orphans.append(&mut self.faces[f].outside);
+        }
+
+        let new_faces: Vec<_> = horizon
+            .iter()
+            .map(|&(a, b)| self.add_face([a, b, eye]))
+            .collect::<Option<_>>()?;
+
+        self.assign(orphans.into_iter().filter(|&p| p != eye), &new_faces);
+
+        Some(new_faces)
+    }
+}
+
+/// Computes the convex hull of `points` with the quickhull algorithm. Returns the triangles of the
+/// hull as indices into `points`, counterclockwise seen from outside, or `None` if all points lie
+/// in a plane or are too close to it to build a consistent hull.
+pub fn convex_hull(points: &[[f64; 3]]) -> Option<Vec<[usize; 3]>> {
+    let points: Vec<Vector3<f64>> = points.iter().map(|&p| p.into()).collect();
+
+    let scale = points
+        .iter()
+        .flat_map(|p| [p.x.abs(), p.y.abs(), p.z.abs()])
+        .fold(0.0, f64::max);
+    let eps = scale.max(1.0) * 1e-9;
+
+    // an initial tetrahedron from extreme points
+    let farthest = |dist: &dyn Fn(Vector3<f64>) -> f64| {
+        (0..points.len())
+            .max_by(|&a, &b| dist(points[a]).total_cmp(&dist(points[b])))
+            .filter(|&i| dist(points[i]) > eps)
+    };
+
+    let a = (0..points.len()).min_by(|&a, &b| points[a].x.total_cmp(&points[b].x))?;
+    let b = farthest(&|p| (p - points[a]).magnitude())?;
+    let ab = (points[b] - points[a]).normalize();
+    let c = farthest(&|p| (p - points[a]).cross(ab).magnitude())?;
+    let normal = ab.cross(points[c] - points[a]).normalize();
+    let d = farthest(&|p| normal.dot(p - points[a]).abs())?;
+
+    // orient the base so that the fourth point is behind it
+    let (b, c) = if normal.dot(points[d] - points[a]) > 0.0 {
+        (c, b)
+    } else {
+        (b, c)
+    };
+
+    let mut hull = Hull {
+        points: &points,
+        eps,
+        faces: Vec::new(),
+        edges: HashMap::new(),
+    };
+
+    let initial = [[a, b, c], [a, d, b], [b, d, c], [c, d, a]]
+        .map(|f| hull.add_face(f))
+        .into_iter()
+        .collect::<Option<Vec<_>>>()?;
+    hull.assign(
+        (0..points.len()).filter(|p| ![a, b, c, d].contains(p)),
+        &initial,
+    );
+
+    let mut pending = initial;
+    while let Some(f) = pending.pop() {
+        let face = &hull.faces[f];
+        if !face.alive || face.outside.is_empty() {
+            continue;
+        }
+
+        let eye = *face
+            .outside
+            .iter()
+            .max_by(|&&p, &&q| {
+                face.distance(points[p])
+                    .total_cmp(&face.distance(points[q]))
+            })
+            .unwrap();
+
+        pending.extend(hull.add_point(f, eye)?);
+    }
+
+    Some(
+        hull.faces
+            .iter()
+            .filter(|f| f.alive)
+            .map(|f| f.vertices)
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn check_hull(points: &[[f64; 3]], triangles: &[[usize; 3]]) {
+        // every point is on or below every face
+        for t in triangles {
+            let face = Face::new(&points.iter().map(|&p| p.into()).collect::<Vec<_>>(), *t)
+                .expect("degenerate face");
+            for p in points {
+                assert!(face.distance((*p).into()) < 1e-9, "{:?} above {:?}", p, t);
+            }
+        }
+
+        // every edge is shared by exactly two faces, in opposite directions
+        let mut edges = HashMap::new();
+        for &[a, b, c] in triangles {
+            for edge in [(a, b), (b, c), (c, a)] {
+                *edges.entry(edge).or_insert(0) += 1;
+            }
+        }
+        for (&(a, b), &count) in &edges {
+            assert_eq!(count, 1);
+            assert_eq!(edges.get(&(b, a)), Some(&1));
+        }
+    }
+
+    #[test]
+    fn test_cube() {
+        let mut points = Vec::new();
+        for i in 0..8 {
+            points.push([i & 1, (i >> 1) & 1, (i >> 2) & 1].map(|x| x as f64));
+        }
+        // inner points and points on faces do not become vertices
+        points.push([0.5, 0.5, 0.5]);
+        points.push([0.5, 0.5, 1.0]);
+        points.push([0.25, 0.75, 0.1]);
+
+        let triangles = convex_hull(&points).unwrap();
+        check_hull(&points, &triangles);
+        assert_eq!(triangles.len(), 12);
+        assert!(triangles.iter().flatten().all(|&i| i < 8));
+    }
+
+    #[test]
+    fn test_sphere() {
+        let mut points = Vec::new();
+        for i in 0..20 {
+            for j in 0..20 {
+                let (theta, phi) = (i as f64 * 0.3, j as f64 * 0.16);
+                points.push([
+                    phi.sin() * theta.cos() * 3.0 + 1.0,
+                    phi.sin() * theta.sin() * 3.0,
+                    phi.cos() * 3.0 - 2.0,
+                ]);
+            }
+        }
+
+        check_hull(&points, &convex_hull(&points).unwrap());
+    }
+
+    #[test]
+    fn test_coplanar_duplicates() {
+        // every corner twice, a grid on each face and points along the edges
+        let mut points = Vec::new();
+        for i in 0..16 {
+            points.push([i & 1, (i >> 1) & 1, (i >> 2) & 1].map(|x| x as f64 * 2.0));
+        }
+        for u in 0..5 {
+            for v in 0..5 {
+                let (u, v) = (u as f64 * 0.5, v as f64 * 0.5);
+                for w in [0.0, 2.0] {
+                    points.extend([[u, v, w], [u, w, v], [w, u, v]]);
+                }
+            }
+        }
+
+        let triangles = convex_hull(&points).unwrap();
+        check_hull(&points, &triangles);
+        assert_eq!(triangles.len(), 12);
+        assert!(triangles
+            .iter()
+            .flatten()
+            .all(|&i| points[i].iter().all(|&x| x == 0.0 || x == 2.0)));
+    }
+
+    #[test]
+    fn test_degenerate() {
+        assert!(convex_hull(&[]).is_none());
+        assert!(convex_hull(&[[1.0, 2.0, 3.0]; 5]).is_none());
+        assert!(convex_hull(&[[0.0; 3], [1.0, 1.0, 1.0], [2.0, 2.0, 2.0]]).is_none());
+        assert!(
+            convex_hull(&[[0.0; 3], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 0.0]]).is_none()
+        );
+    }
+}
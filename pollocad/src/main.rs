@@ -7,14 +7,27 @@ use eframe::egui;
 
 mod ast;
 mod builtins;
+mod cli;
+mod diagnostics;
+mod disk_cache;
+mod export;
 mod geometry;
+mod hull;
 mod parser;
 //mod preview;
 mod runtime;
+mod stl;
+mod worker;
 
-use pollocad_occt::{CascadePreview, MouseFlags};
+use pollocad_occt::{CascadePreview, MouseFlags, Shape};
+
+use diagnostics::Diagnostic;
 
 fn main() -> Result<(), eframe::Error> {
+    if let Some(code) = cli::run() {
+        std::process::exit(code);
+    }
+
     let options = eframe::NativeOptions {
         initial_window_size: Some(egui::vec2(1600.0, 800.0)),
         multisampling: 1,
@@ -33,9 +46,19 @@ fn main() -> Result<(), eframe::Error> {
 pub struct MyApp {
     code: String,
     preview: Arc<Mutex<pollocad_occt::CascadePreview>>,
+    worker: worker::Worker,
+    log: Vec<String>,
+    diagnostics: Vec<Diagnostic>,
+    shape: Option<Arc<Shape>>,
+    // the latest code produced no shape, so the preview still shows an older one
+    shape_stale: bool,
+    export_path: String,
+    export_status: String,
     num_indices: u32,
     num_vertices: u32,
     valid: bool,
+    parallel_booleans: bool,
+    disk_cache: bool,
 }
 
 const CODE: &'static str = r#"
@@ -55,44 +78,230 @@ impl MyApp {
         Some(Self {
             code: CODE.to_string(),
             preview: Arc::new(Mutex::new(CascadePreview::new(&cc).expect("create preview failed"))),
+            worker: worker::Worker::new({
+                let ctx = cc.egui_ctx.clone();
+                move || ctx.request_repaint()
+            }),
+            log: Vec::new(),
+            diagnostics: Vec::new(),
+            shape: None,
+            shape_stale: false,
+            export_path: "export.step".to_string(),
+            export_status: String::new(),
             num_indices: 0,
             num_vertices: 0,
             valid: false,
+            parallel_booleans: false,
+            disk_cache: true,
         })
     }
+
+    fn export(&mut self) {
+        self.export_status = match &self.shape {
+            Some(_) if self.shape_stale => "Nothing to export for the current code".to_string(),
+            Some(shape) => {
+                let path = std::path::Path::new(&self.export_path);
+                match export::export(shape, path, &Default::default()) {
+                    Ok(()) => format!("Exported {}", path.display()),
+                    Err(e) => e,
+                }
+            }
+            None => "Nothing to export".to_string(),
+        };
+    }
+}
+
+/// Lays out the code with the ranges of the diagnostics underlined.
+fn layout_code(ui: &egui::Ui, text: &str, wrap_width: f32, diagnostics: &[Diagnostic]) -> Arc<egui::Galley> {
+    let font_id = egui::FontSelection::default().resolve(ui.style());
+    let color = ui.visuals().widgets.inactive.text_color();
+    let underline = egui::Stroke::new(1.5, ui.visuals().error_fg_color);
+
+    // The diagnostics may be from the previous version of the text, so work on bytes to avoid
+    // slicing in the middle of a character.
+    let mut marked = vec![false; text.len()];
+    for d in diagnostics {
+        let start = d.range.start.min(text.len());
+        let end = d.range.end.min(text.len());
+
+        // Widen empty ranges so that there is something to underline
+        let range = if start < end {
+            start..end
+        } else if start < text.len() {
+            start..start + 1
+        } else {
+            start.saturating_sub(1)..start
+        };
+
+        marked[range].fill(true);
+    }
+
+    let mut job = egui::text::LayoutJob::default();
+    job.wrap.max_width = wrap_width;
+
+    let mut run: Option<(usize, bool)> = None;
+    for (i, c) in text.char_indices() {
+        let is_marked = marked[i..i + c.len_utf8()].contains(&true);
+
+        match run {
+            Some((start, m)) if m != is_marked => {
+                job.append(&text[start..i], 0.0, egui::TextFormat {
+                    font_id: font_id.clone(),
+                    color,
+                    underline: if m { underline } else { egui::Stroke::NONE },
+                    ..Default::default()
+                });
+                run = Some((i, is_marked));
+            }
+            None => run = Some((i, is_marked)),
+            _ => {}
+        }
+    }
+
+    if let Some((start, m)) = run {
+        job.append(&text[start..], 0.0, egui::TextFormat {
+            font_id,
+            color,
+            underline: if m { underline } else { egui::Stroke::NONE },
+            ..Default::default()
+        });
+    }
+
+    ui.fonts(|f| f.layout_job(job))
 }
 
 impl eframe::App for MyApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        if let Some(output) = self.worker.poll() {
+            match output.shape {
+                Some(shape) => {
+                    self.preview.lock().unwrap().set_shape(&shape).expect("set_shape failed");
+                    self.shape = Some(shape);
+                    self.shape_stale = false;
+                }
+                None => self.shape_stale = true,
+            }
+
+            self.log = output.log;
+            self.diagnostics = output.diagnostics;
+        }
+
+        egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
+            egui::menu::bar(ui, |ui| {
+                ui.menu_button("File", |ui| {
+                    ui.menu_button("Export", |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("File:");
+                            ui.text_edit_singleline(&mut self.export_path);
+                        });
+
+                        if ui.button("Export (.step, .brep, .stl)").clicked() {
+                            self.export();
+                            ui.close_menu();
+                        }
+                    });
+                });
+
+                ui.menu_button("Settings", |ui| {
+                    if ui.checkbox(&mut self.parallel_booleans, "Parallel booleans").changed() {
+                        self.worker.set_parallel_booleans(self.parallel_booleans);
+                    }
+
+                    if ui.checkbox(&mut self.disk_cache, "Disk cache").changed() {
+                        self.worker.set_disk_cache_enabled(self.disk_cache);
+                    }
+
+                    if ui.button("Clear disk cache").clicked() {
+                        self.export_status = match self.worker.clear_disk_cache() {
+                            Ok(()) => "Cleared disk cache".to_string(),
+                            Err(e) => e,
+                        };
+                        ui.close_menu();
+                    }
+                });
+
+                ui.label(&self.export_status);
+
+                if let Some(started) = self.worker.busy_since() {
+                    ui.separator();
+                    ui.spinner();
+                    ui.label(format!("computing… {:.1} s", started.elapsed().as_secs_f64()));
+
+                    // keep the elapsed time ticking
+                    ctx.request_repaint_after(std::time::Duration::from_millis(100));
+                } else if self.shape_stale && self.shape.is_some() {
+                    ui.separator();
+                    ui.colored_label(ui.visuals().warn_fg_color, "preview is out of date");
+                }
+            });
+        });
+
+        egui::TopBottomPanel::bottom("console_panel")
+            .resizable(true)
+            .default_height(120.0)
+            .show(ctx, |ui| {
+                egui::ScrollArea::vertical()
+                    .auto_shrink([false, false])
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        for line in &self.log {
+                            ui.monospace(line);
+                        }
+                    });
+            });
+
         egui::SidePanel::left("code_panel")
             .resizable(true)
             .default_width(400.0)
             .show(ctx, |ui| {
+                let code_id = egui::Id::new("code");
+                let mut jump_to = None;
+
+                if !self.diagnostics.is_empty() {
+                    egui::TopBottomPanel::bottom("diagnostics_panel").show_inside(ui, |ui| {
+                        for d in &self.diagnostics {
+                            let text = egui::RichText::new(d.to_string()).color(ui.visuals().error_fg_color);
+                            if ui.link(text).clicked() {
+                                jump_to = Some(d.range.clone());
+                            }
+                        }
+                    });
+                }
+
                 ui.with_layout(
                     egui::Layout::top_down_justified(egui::Align::Min).with_main_justify(true),
                     |ui| {
-                        let response =
-                            ui.add(egui::TextEdit::multiline(&mut self.code).frame(false));
+                        let diagnostics = &self.diagnostics;
+                        let mut layouter = |ui: &egui::Ui, text: &str, wrap_width: f32| {
+                            layout_code(ui, text, wrap_width, diagnostics)
+                        };
+
+                        let response = ui.add(
+                            egui::TextEdit::multiline(&mut self.code)
+                                .id(code_id)
+                                .frame(false)
+                                .layouter(&mut layouter),
+                        );
 
                         if response.changed() || !self.valid {
                             self.valid = true;
-                            match parser::parse_source(&self.code) {
-                                Ok((_, body)) => match runtime::exec(body.as_ref()) {
-                                    Ok(runtime::Value::Solid(geo)) => {
-                                        self.preview.lock().unwrap().set_shape(&*geo.get_single_shape().expect("no shape")).expect("set_shape failed");
-                                    }
-                                    Err(e) => {
-                                        eprintln!("Exec error: {:#?}", e);
-                                    }
-                                    _ => {}
-                                },
-                                Err(e) => {
-                                    eprintln!("Parse error: {:#?}", e);
-                                }
-                            }
+                            self.worker.submit(self.code.clone());
                         }
                     },
                 );
+
+                if let Some(range) = jump_to {
+                    if let Some(mut state) = egui::TextEdit::load_state(ui.ctx(), code_id) {
+                        let char_index = |byte: usize| self.code.char_indices().take_while(|(i, _)| *i < byte).count();
+                        state.set_ccursor_range(Some(egui::text::CCursorRange::two(
+                            egui::text::CCursor::new(char_index(range.start)),
+                            egui::text::CCursor::new(char_index(range.end)),
+                        )));
+                        state.store(ui.ctx(), code_id);
+                    }
+
+                    ui.memory_mut(|m| m.request_focus(code_id));
+                }
             });
 
         egui::CentralPanel::default().show(ctx, |ui| {
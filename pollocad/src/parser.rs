@@ -1,12 +1,13 @@
 #[allow(unused)]
 use nom::{
     branch::alt,
-    bytes::complete::{tag, tag_no_case},
+    bytes::complete::{escaped_transform, is_not, tag, tag_no_case},
     character::complete::{
         alpha1, alphanumeric1, char, digit1, hex_digit1, line_ending, multispace1, none_of,
     },
     combinator::{
-        all_consuming, complete, cut, eof, fail, map, map_res, not, opt, recognize, success, value,
+        all_consuming, complete, cut, eof, fail, map, map_opt, map_res, not, opt, recognize,
+        success, value, verify,
     },
     error::{context, convert_error, VerboseError},
     multi::{fold_many0, many0, many0_count, many1, separated_list0},
@@ -24,28 +25,45 @@ type Span<'a> = LocatedSpan<&'a str>;
 //type Result<'a, T> = nom::IResult<Span<'a>, T, VerboseError<Span<'a>>>;
 type Result<'a, T> = nom::IResult<Span<'a>, T, ErrorDetail<'a>>;
 
+/// Where parsing failed, and a readable message if a context or check gave one.
 #[derive(Clone, Debug)]
-pub struct ErrorDetail<'a>(Span<'a>, String);
+pub struct ErrorDetail<'a>(Span<'a>, Option<String>);
 
+impl ErrorDetail<'_> {
+    /// Byte offset of the error in the source code.
+    pub fn offset(&self) -> usize {
+        self.0.location_offset()
+    }
+
+    pub fn message(&self) -> &str {
+        self.1.as_deref().unwrap_or("unexpected input")
+    }
+}
+
+// nom's error kinds name the combinator that failed, which means nothing to the user, so only the
+// messages from contexts and checks are kept, and the innermost one wins
 impl<'a> nom::error::ParseError<Span<'a>> for ErrorDetail<'a> {
-    fn from_error_kind(input: Span<'a>, kind: nom::error::ErrorKind) -> Self {
-        ErrorDetail(input, format!("ErrorKind: {:?}", kind))
+    fn from_error_kind(input: Span<'a>, _kind: nom::error::ErrorKind) -> Self {
+        ErrorDetail(input, None)
     }
 
-    fn append(input: Span<'a>, kind: nom::error::ErrorKind, other: Self) -> Self {
-        ErrorDetail(input, format!("ErrorKind: {:?}, {}", kind, other.0))
+    fn append(_input: Span<'a>, _kind: nom::error::ErrorKind, other: Self) -> Self {
+        other
     }
 }
 
 impl<'a> nom::error::FromExternalError<Span<'a>, String> for ErrorDetail<'a> {
-    fn from_external_error(input: Span<'a>, kind: nom::error::ErrorKind, err: String) -> Self {
-        ErrorDetail(input, format!("ExternalError: {:?} {}", kind, err))
+    fn from_external_error(input: Span<'a>, _kind: nom::error::ErrorKind, err: String) -> Self {
+        ErrorDetail(input, Some(err))
     }
 }
 
 impl<'a> nom::error::ContextError<Span<'a>> for ErrorDetail<'a> {
-    fn add_context(input: Span<'a>, ctx: &'static str, other: Self) -> Self {
-        ErrorDetail(input, format!("Context: {}, {}", ctx, other.0))
+    fn add_context(_input: Span<'a>, ctx: &'static str, other: Self) -> Self {
+        match other.1 {
+            Some(_) => other,
+            None => ErrorDetail(other.0, Some(ctx.to_string())),
+        }
     }
 }
 
@@ -83,15 +101,29 @@ where
 }
 
 fn node(pos: Range<usize>, expr: Expr) -> Arc<Node> {
-    Arc::new(Node { pos, expr })
+    Arc::new(Node::new(pos, expr))
 }
 
+const KEYWORDS: &[&str] = &["if", "else", "for", "fn"];
+
 fn ident(i: Span) -> Result<Span> {
-    recognize(tuple((
-        opt(tag("$")),
-        alt((alpha1, tag("_"))),
-        many0_count(alt((alphanumeric1, tag("_")))),
-    )))(i)
+    verify(
+        recognize(tuple((
+            opt(tag("$")),
+            alt((alpha1, tag("_"))),
+            many0_count(alt((alphanumeric1, tag("_")))),
+        ))),
+        |s: &Span| !KEYWORDS.contains(s.fragment()),
+    )(i)
+}
+
+fn keyword<'a>(kw: &'static str) -> impl FnMut(Span<'a>) -> Result<'a, Span<'a>> {
+    tws(terminated(tag(kw), not(alt((alphanumeric1, tag("_"))))))
+}
+
+// a single = that isn't the start of ==
+fn assign(i: Span) -> Result<char> {
+    tws(terminated(char('='), not(char('='))))(i)
 }
 
 fn expr_const(i: Span) -> Result<Arc<Node>> {
@@ -109,8 +141,54 @@ fn expr_var(i: Span) -> Result<Arc<Node>> {
     ))(i)
 }
 
+fn expr_string(i: Span) -> Result<Arc<Node>> {
+    map(
+        tws(pos(delimited(
+            char('"'),
+            map(
+                opt(escaped_transform(
+                    is_not("\\\""),
+                    '\\',
+                    alt((
+                        value('\\', char('\\')),
+                        value('"', char('"')),
+                        value('\n', char('n')),
+                        value('\r', char('r')),
+                        value('\t', char('t')),
+                        map_opt(delimited(tag("u{"), hex_digit1, char('}')), |hex: Span| {
+                            u32::from_str_radix(hex.fragment(), 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                        }),
+                    )),
+                )),
+                Option::unwrap_or_default,
+            ),
+            cut(context("expected closing \"", char('"'))),
+        ))),
+        |(pos, s)| node(pos, Expr::Str(s)),
+    )(i)
+}
+
+fn expr_list(i: Span) -> Result<Arc<Node>> {
+    map(
+        tws(pos(delimited(
+            tws(tag("[")),
+            terminated(separated_list0(tws(tag(",")), expr), opt(tws(tag(",")))),
+            tag("]"),
+        ))),
+        |(pos, items)| node(pos, Expr::List(items)),
+    )(i)
+}
+
 fn expr_parens(i: Span) -> Result<Arc<Node>> {
-    alt((delimited(tws(tag("(")), expr, tws(tag(")"))), expr_var))(i)
+    alt((
+        delimited(tws(tag("(")), expr, tws(tag(")"))),
+        expr_string,
+        expr_range,
+        expr_list,
+        expr_var,
+    ))(i)
 }
 
 /*fn expr_call(i: &str) -> Result<Expr> {
@@ -120,8 +198,84 @@ fn expr_parens(i: Span) -> Result<Arc<Node>> {
     ))(i)
 }*/
 
+fn expr_if(i: Span) -> Result<Arc<Node>> {
+    map(
+        pos(tuple((
+            preceded(
+                keyword("if"),
+                cut(delimited(tws(tag("(")), expr, tws(tag(")")))),
+            ),
+            cut(block),
+            opt(preceded(
+                keyword("else"),
+                cut(alt((map(expr_if, |n| vec![n]), block))),
+            )),
+        ))),
+        |(pos, (cond, then_body, else_body))| {
+            node(
+                pos,
+                Expr::If(IfExpr {
+                    cond,
+                    then_body,
+                    else_body,
+                }),
+            )
+        },
+    )(i)
+}
+
+fn expr_range(i: Span) -> Result<Arc<Node>> {
+    map(
+        tws(pos(delimited(
+            tws(tag("[")),
+            tuple((
+                expr,
+                preceded(tws(tag(":")), expr),
+                opt(preceded(tws(tag(":")), expr)),
+            )),
+            tag("]"),
+        ))),
+        |(pos, (start, second, third))| {
+            let (step, end) = match third {
+                Some(end) => (Some(second), end),
+                None => (None, second),
+            };
+
+            node(pos, Expr::Range(RangeExpr { start, step, end }))
+        },
+    )(i)
+}
+
+fn expr_for(i: Span) -> Result<Arc<Node>> {
+    map(
+        pos(tuple((
+            preceded(
+                keyword("for"),
+                cut(delimited(
+                    tws(tag("(")),
+                    pair(tws(ident), preceded(assign, expr)),
+                    tws(tag(")")),
+                )),
+            ),
+            cut(alt((map(expr_call, |child| vec![child]), block))),
+        ))),
+        |(pos, ((var, iter), body))| {
+            node(
+                pos,
+                Expr::For(ForExpr {
+                    var: var.to_string(),
+                    iter,
+                    body,
+                }),
+            )
+        },
+    )(i)
+}
+
 fn expr_call(i: Span) -> Result<Arc<Node>> {
     alt((
+        expr_if,
+        expr_for,
         map(
             pos(tuple((
                 ident,
@@ -133,7 +287,7 @@ fn expr_call(i: Span) -> Result<Arc<Node>> {
                                 tws(tag(",")),
                                 alt((
                                     map(
-                                        pair(tws(ident), preceded(tws(char('=')), expr)),
+                                        pair(tws(ident), preceded(assign, expr)),
                                         |(name, value)| (Some(name.to_string()), value),
                                     ),
                                     map(expr, |expr| (None, expr)),
@@ -165,7 +319,7 @@ fn expr_call(i: Span) -> Result<Arc<Node>> {
                             Ok(args)
                         },
                     )),
-                    tws(cut(context("closing paren", tag(")")))),
+                    tws(cut(context("expected )", tag(")")))),
                 ),
                 alt((map(expr_call, |child| vec![child]), block, success(vec![]))),
             ))),
@@ -212,28 +366,160 @@ fn binop<'a>(
     )(i)
 }
 
+fn expr_index(i: Span) -> Result<Arc<Node>> {
+    let (i, init) = expr_call(i)?;
+
+    fold_many0(
+        tws(pos(delimited(tws(tag("[")), expr, tag("]")))),
+        move || init.clone(),
+        |prev, (pos, index)| {
+            node(
+                pos,
+                Expr::Call(CallExpr {
+                    name: "[]".to_string(),
+                    args: vec![(None, prev), (None, index)],
+                    body: vec![],
+                }),
+            )
+        },
+    )(i)
+}
+
+fn expr_unary(i: Span) -> Result<Arc<Node>> {
+    alt((
+        expr_index,
+        map(
+            pair(tws(pos(alt((tag("-"), tag("+"), tag("!"))))), expr_unary),
+            |((pos, op), operand)| {
+                let name = match *op.fragment() {
+                    "!" => "!".to_string(),
+                    op => format!("unary{}", op),
+                };
+
+                node(
+                    pos,
+                    Expr::Call(CallExpr {
+                        name,
+                        args: vec![(None, operand)],
+                        body: vec![],
+                    }),
+                )
+            },
+        ),
+    ))(i)
+}
+
 fn expr_mul_div(i: Span) -> Result<Arc<Node>> {
-    binop(i, expr_call, alt((tag("*"), tag("/"), tag("%"))))
+    binop(i, expr_unary, alt((tag("*"), tag("/"), tag("%"))))
 }
 
 fn expr_add_sub(i: Span) -> Result<Arc<Node>> {
     binop(i, expr_mul_div, alt((tag("+"), tag("-"))))
 }
 
+fn expr_cmp(i: Span) -> Result<Arc<Node>> {
+    binop(i, expr_add_sub, alt((tag("<="), tag(">="), tag("<"), tag(">"))))
+}
+
+fn expr_eq(i: Span) -> Result<Arc<Node>> {
+    binop(i, expr_cmp, alt((tag("=="), tag("!="))))
+}
+
+fn expr_and(i: Span) -> Result<Arc<Node>> {
+    binop(i, expr_eq, tag("&&"))
+}
+
+fn expr_or(i: Span) -> Result<Arc<Node>> {
+    binop(i, expr_and, tag("||"))
+}
+
 fn expr(i: Span) -> Result<Arc<Node>> {
-    tws(expr_add_sub)(i)
+    tws(expr_or)(i)
+}
+
+// fn name(params) { body } is sugar for name = <function>; with the rest of the block as the body.
+// The fn keyword is required: without it `part(w, h = 2) { ... }` is indistinguishable from a call
+// with children such as `translate(v) { cube(); }`, since parameter lists are valid argument lists.
+fn fn_def(i: Span) -> Result<Arc<Node>> {
+    map(
+        pos(preceded(
+            keyword("fn"),
+            cut(tuple((
+                tws(ident),
+                delimited(
+                    tws(tag("(")),
+                    map_res(
+                        terminated(
+                            separated_list0(
+                                tws(tag(",")),
+                                pair(tws(ident), opt(preceded(assign, expr))),
+                            ),
+                            opt(tws(tag(","))),
+                        ),
+                        |params| {
+                            let mut names: HashSet<&str> = HashSet::new();
+
+                            for (name, _) in &params {
+                                if !names.insert(name.fragment()) {
+                                    return Err(format!("duplicate parameter: {}", name));
+                                }
+                            }
+
+                            Ok(params)
+                        },
+                    ),
+                    tws(context("expected )", tag(")"))),
+                ),
+                block,
+            ))),
+        )),
+        |(pos, (name, params, body))| {
+            let name = name.to_string();
+
+            node(
+                pos.clone(),
+                Expr::Let(LetExpr {
+                    name: name.clone(),
+                    value: node(
+                        pos,
+                        Expr::Func(FuncExpr {
+                            name,
+                            params: params
+                                .into_iter()
+                                .map(|(name, default)| (name.to_string(), default))
+                                .collect(),
+                            body,
+                        }),
+                    ),
+                    body: vec![],
+                }),
+            )
+        },
+    )(i)
+}
+
+// statements that don't need to be terminated with a semicolon; a bare call like `a()` must
+// not match here, otherwise a trailing `a()` would be taken as a statement rather than the
+// block's return value
+fn is_block_statement(node: &Arc<Node>) -> bool {
+    match &node.expr {
+        Expr::Call(call) => !call.body.is_empty(),
+        Expr::If(_) | Expr::For(_) => true,
+        _ => false,
+    }
 }
 
 fn block_body(i: Span) -> Result<Vec<Arc<Node>>> {
     map(
         pair(
             many0(alt((
+                fn_def,
                 terminated(
                     alt((
                         map(
                             pos(pair(
                                 tws(ident),
-                                preceded(tws(char('=')), context("Missing ; 3", cut(expr))),
+                                preceded(assign, context("expected an expression", cut(expr))),
                             )),
                             |(pos, (name, value))| {
                                 node(
@@ -250,8 +536,8 @@ fn block_body(i: Span) -> Result<Vec<Arc<Node>>> {
                     )),
                     many1(tws(tag(";"))),
                 ),
-                expr_call,
-                context("Missing ; 2", fail),
+                verify(expr_call, is_block_statement),
+                context("expected ;", fail),
             ))),
             opt(expr),
         ),
@@ -260,14 +546,18 @@ fn block_body(i: Span) -> Result<Vec<Arc<Node>>> {
                 nodes.push(node(r.pos.clone(), Expr::Return(r)));
             }
 
+            // walk backwards so each let can take the statements after it as its body, then
+            // restore source order (both for the let bodies and the block itself)
             let mut result = vec![];
             for n in nodes.into_iter().rev() {
                 if let Node {
                     pos,
                     expr: Expr::Let(LetExpr { name, value, .. }),
+                    ..
                 } = &*n
                 {
-                    let body = std::mem::take(&mut result);
+                    let mut body = std::mem::take(&mut result);
+                    body.reverse();
                     result.push(node(
                         pos.clone(),
                         Expr::Let(LetExpr {
@@ -281,6 +571,7 @@ fn block_body(i: Span) -> Result<Vec<Arc<Node>>> {
                 }
             }
 
+            result.reverse();
             result
         },
     )(i)
@@ -296,40 +587,226 @@ pub fn parse_source(i: &str) -> Result<Vec<Arc<Node>>> {
 
 #[cfg(test)]
 mod test {
-    //use super::*;
+    use super::*;
+
+    fn sexpr(node: &Node) -> String {
+        match &node.expr {
+            Expr::Let(let_) => format!(
+                "(let {} {}{})",
+                let_.name,
+                sexpr(&let_.value),
+                let_.body.iter().map(|n| format!(" {}", sexpr(n))).collect::<String>()
+            ),
+            Expr::Call(call) => format!(
+                "({}{}{})",
+                call.name,
+                call.args
+                    .iter()
+                    .map(|(name, n)| match name {
+                        Some(name) => format!(" {}={}", name, sexpr(n)),
+                        None => format!(" {}", sexpr(n)),
+                    })
+                    .collect::<String>(),
+                if call.body.is_empty() {
+                    String::new()
+                } else {
+                    format!(" {{{}}}", call.body.iter().map(|n| sexpr(n)).collect::<Vec<_>>().join(" "))
+                }
+            ),
+            Expr::Var(name) => name.clone(),
+            Expr::Num(num) => num.to_string(),
+            Expr::Str(s) => format!("{:?}", s),
+            Expr::List(items) => format!(
+                "[{}]",
+                items
+                    .iter()
+                    .map(|n| sexpr(n))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Expr::If(if_) => format!(
+                "(if {} {{{}}}{})",
+                sexpr(&if_.cond),
+                if_.then_body.iter().map(|n| sexpr(n)).collect::<Vec<_>>().join(" "),
+                match &if_.else_body {
+                    Some(body) => format!(
+                        " {{{}}}",
+                        body.iter().map(|n| sexpr(n)).collect::<Vec<_>>().join(" ")
+                    ),
+                    None => String::new(),
+                }
+            ),
+            Expr::For(for_) => format!(
+                "(for {} {} {{{}}})",
+                for_.var,
+                sexpr(&for_.iter),
+                for_.body.iter().map(|n| sexpr(n)).collect::<Vec<_>>().join(" ")
+            ),
+            Expr::Range(range) => format!(
+                "[{}{}:{}]",
+                sexpr(&range.start),
+                range.step.as_ref().map(|s| format!(":{}", sexpr(s))).unwrap_or_default(),
+                sexpr(&range.end)
+            ),
+            Expr::Func(func) => format!(
+                "(fn {}({}) {{{}}})",
+                func.name,
+                func.params
+                    .iter()
+                    .map(|(name, default)| match default {
+                        Some(d) => format!("{}={}", name, sexpr(d)),
+                        None => name.clone(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                func.body.iter().map(|n| sexpr(n)).collect::<Vec<_>>().join(" ")
+            ),
+            Expr::Return(n) => format!("(return {})", sexpr(n)),
+        }
+    }
 
-    /*fn check(code: &str, result: Vec<Arc<Node>>) {
+    fn check(code: &str, expected: &str) {
         match parse_source(code) {
             Ok((_, out)) => {
-                println!("{:#?}", out);
-                assert_eq!(out, result);
-            }
-            Err(nom::Err::Error(e) | nom::Err::Failure(e)) => {
-                //panic!("{}", convert_error(code, e));
+                assert_eq!(
+                    out.iter().map(|n| sexpr(n)).collect::<Vec<_>>().join(" "),
+                    expected
+                );
             }
-            Err(nom::Err::Incomplete(_)) => panic!("Incomplete"),
+            Err(e) => panic!("parse failed: {:?}", e),
         }
-    }*/
+    }
 
     #[test]
     fn test_parse_source() {
-        //check("", vec![]);
+        check("", "");
+        check("x = 1; a(x + 1) { x }", "(let x 1 (a (+ x 1) {(return x)}))");
+        check("a(); x = 1; b(); c(); x", "(a) (let x 1 (b) (c) (return x))");
+        check("a(y * 2 + 3, a=x) { 1 + 2 } b();", "(a (+ (* y 2) 3) a=x {(return (+ 1 2))}) (b)");
+    }
+
+    #[test]
+    fn test_statement_order() {
+        check("a(); b(); c();", "(a) (b) (c)");
+        check("a(); b(); c()", "(a) (b) (return (c))");
+        check(
+            "x = 1; a(); y = 2; b(); c()",
+            "(let x 1 (a) (let y 2 (b) (return (c))))",
+        );
+        check("a() { b(); c(); } d()", "(a {(b) (c)}) (return (d))");
+        check("if (x) { a(); } b(); c()", "(if x {(a)}) (b) (return (c))");
+    }
+
+    #[test]
+    fn test_node_info() {
+        let (_, body) =
+            parse_source("fn f(a, b = a + c) { a + d } f(x, [sphere()]) { cube(); }").unwrap();
+        assert_eq!(body[0].info.free_vars, ["+", "c", "cube", "d", "sphere", "x"]);
+        assert_eq!(body[0].info.calls, ["cube", "f"]);
+
+        // positions don't matter
+        let (_, a) = parse_source("cube(1);").unwrap();
+        let (_, b) = parse_source("  cube( 1 );").unwrap();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_unary() {
+        check("-a", "(return (unary- a))");
+        check("-5", "(return -5)");
+        check("-(a + b)", "(return (unary- (+ a b)))");
+        check("+a * -b", "(return (* (unary+ a) (unary- b)))");
+        check("a - -b", "(return (- a (unary- b)))");
+        check("!!a", "(return (! (! a)))");
+        check("translate(x=-a) cube();", "(translate x=(unary- a) {(cube)})");
+    }
+
+    #[test]
+    fn test_comparison_and_logic() {
+        check("a + 1 < b * 2", "(return (< (+ a 1) (* b 2)))");
+        check("a <= b == c >= d", "(return (== (<= a b) (>= c d)))");
+        check("a || b && !c", "(return (|| a (&& b (! c))))");
+        check("a != b || a == b", "(return (|| (!= a b) (== a b)))");
+        check("x = a == b; f(a == b, y=a != b);", "(let x (== a b) (f (== a b) y=(!= a b)))");
+    }
+
+    #[test]
+    fn test_if() {
+        check("if (a > 1) { cube(); }", "(if (> a 1) {(cube)})");
+        check(
+            "if (a) { cube(); } else if (b) { 1 } else { x = 2; x } c();",
+            "(if a {(cube)} {(if b {(return 1)} {(let x 2 (return x))})}) (c)",
+        );
+        check("x = if (a) { 1 } else { 2 }; x", "(let x (if a {(return 1)} {(return 2)}) (return x))");
+        check("iffy(elsewhere);", "(iffy elsewhere)");
+        assert!(parse_source("if (a) cube();").is_err());
+    }
 
-        //panic!("{:#?}", parse_source("x = 1; b = 2; a(2, 3, a=2); 2+ 1"));
+    #[test]
+    fn test_for() {
+        check(
+            "for (i = [0:n - 1]) { translate(x=i * 10) cube(); }",
+            "(for i [0:(- n 1)] {(translate x=(* i 10) {(cube)})})",
+        );
+        check(
+            "for (i = [0 : 2 : 10]) translate(x=i) cube(); a();",
+            "(for i [0:2:10] {(translate x=i {(cube)})}) (a)",
+        );
+        check("format(fork);", "(format fork)");
+    }
 
-        /*check(
-            "x = 1; b = 2; a(x + 1) { x }",
-            vec![Expr::Let(
-                String::from("x"),
-                Box::new(Expr::Num(1.0)),
-                vec![],
-            )],
+    #[test]
+    fn test_list() {
+        check("[]", "(return [])");
+        check("[1, a + 1, [b],]", "(return [1, (+ a 1), [b]])");
+        check(
+            "v[0] * -v[i + 1][2]",
+            "(return (* ([] v 0) (unary- ([] ([] v (+ i 1)) 2))))",
+        );
+        check("[1, 2, 3][1]", "(return ([] [1, 2, 3] 1))");
+        check("x = [0:2:10]; x", "(let x [0:2:10] (return x))");
+        check(
+            "for (p = [[1, 2], [3, 4]]) translate(p) cube();",
+            "(for p [[1, 2], [3, 4]] {(translate p {(cube)})})",
         );
+        check(
+            "translate([1, 2, 3]) cube([1, 1, 1]);",
+            "(translate [1, 2, 3] {(cube [1, 1, 1])})",
+        );
+    }
+
+    #[test]
+    fn test_error_messages() {
+        let error = |code: &str| match parse_source(code) {
+            Err(nom::Err::Error(e) | nom::Err::Failure(e)) => (e.offset(), e.message().to_string()),
+            _ => panic!("{} did not fail", code),
+        };
+
+        assert_eq!(error("cube(1;"), (6, "expected )".to_string()));
+        assert_eq!(error("x = ;"), (4, "expected an expression".to_string()));
+        assert_eq!(error(r#"echo("abc);"#), (11, "expected closing \"".to_string()));
+        assert_eq!(error("f(a=1, a=2);"), (2, "duplicate named argument: a".to_string()));
+        assert_eq!(error("cube() )"), (7, "unexpected input".to_string()));
+    }
 
+    #[test]
+    fn test_string() {
+        check(r#"echo("", "a b", x);"#, r#"(echo "" "a b" x)"#);
+        check(r#""tab\t \"quoted\" \\ \u{e9}\n""#, r#"(return "tab\t \"quoted\" \\ é\n")"#);
+        check(r#"["a", "]"][0]"#, r#"(return ([] ["a", "]"] 0))"#);
+        assert!(parse_source(r#""unterminated"#).is_err());
+        assert!(parse_source(r#""bad \q escape""#).is_err());
+    }
+
+    #[test]
+    fn test_fn() {
         check(
-            "x = 1; /* hi */
-        a(a=x, y * 2 + 3) { 1 + 2 } a(a()) b();",
-            vec![],
-        );*/
+            "fn plate(w, h = w * 2,) { cube(w, h, 1); } plate(2);",
+            "(let plate (fn plate(w h=(* w 2)) {(cube w h 1)}) (plate 2))",
+        );
+        check("fn one() { 1 } fnord = one(); fnord", "(let one (fn one() {(return 1)}) (let fnord (one) (return fnord)))");
+        check("f(w, h = 2) { w }", "(f w h=2 {(return w)})");
+        assert!(parse_source("fn f(a, a) { }").is_err());
+        assert!(parse_source("fn f(a) cube();").is_err());
     }
 }
@@ -1,34 +1,187 @@
 use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
 
-use fxhash::FxBuildHasher;
+use fxhash::{FxBuildHasher, FxHasher64};
 use threadpool::ThreadPool;
 
 use crate::ast::*;
+use crate::disk_cache::{DiskCache, KeyHasher};
 use crate::geometry::Solid;
 
 type Result = std::result::Result<Value, Error>;
 
 struct CacheEntry {
     used: bool,
+    /// Compared on a hit, since different keys may hash the same.
+    key: CacheKey,
     value: Value,
+    /// Messages logged while computing the value. A cache hit logs them again.
+    log: Vec<String>,
+    /// Keys of the cached results this one was computed from. They are kept alive for as long as
+    /// this entry is, even though a cache hit skips evaluating them.
+    deps: Vec<usize>,
 }
 
 pub struct Runtime {
     pool: ThreadPool,
     cache: RwLock<HashMap<usize, CacheEntry, FxBuildHasher>>,
+    log: Mutex<Vec<String>>,
+    // kept for the lifetime of the runtime so that builtin functions hash the same in every
+    // evaluation
+    builtins: HashMap<String, Value>,
+    disk_cache: Option<DiskCache>,
+    disk_cache_enabled: AtomicBool,
+    /// Whether OCCT may run unions and intersections on its own worker threads. This is off by
+    /// default, since the runtime already evaluates independent operations in parallel.
+    parallel_booleans: AtomicBool,
+    /// Number of tasks the thread pool has run, as opposed to the threads that joined them.
+    #[cfg(test)]
+    pool_runs: std::sync::atomic::AtomicUsize,
 }
 
 impl Runtime {
-    fn new() -> Runtime {
+    pub fn new() -> Runtime {
+        Runtime::with_disk_cache(None)
+    }
+
+    /// Creates a runtime that also stores the results of the heaviest operations on disk.
+    pub fn with_disk_cache(disk_cache: Option<DiskCache>) -> Runtime {
         Runtime {
-            pool: ThreadPool::new(8),
+            pool: threadpool::Builder::new()
+                .num_threads(8)
+                .thread_stack_size(EVAL_STACK_SIZE)
+                .build(),
             cache: RwLock::new(HashMap::with_hasher(FxBuildHasher::default())),
+            log: Mutex::new(Vec::new()),
+            builtins: crate::builtins::get_builtins(),
+            disk_cache,
+            disk_cache_enabled: AtomicBool::new(true),
+            parallel_booleans: AtomicBool::new(false),
+            #[cfg(test)]
+            pool_runs: Default::default(),
+        }
+    }
+
+    /// Returns the on-disk cache, unless there is none or it has been disabled.
+    fn disk_cache(&self) -> Option<&DiskCache> {
+        self.disk_cache
+            .as_ref()
+            .filter(|_| self.disk_cache_enabled.load(Ordering::Relaxed))
+    }
+
+    /// Sets whether the on-disk cache is used, from the next evaluation on.
+    pub fn set_disk_cache_enabled(&self, enabled: bool) {
+        self.disk_cache_enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Removes every entry from the on-disk cache, if there is one.
+    pub fn clear_disk_cache(&self) -> std::result::Result<(), String> {
+        match &self.disk_cache {
+            Some(cache) => cache
+                .clear()
+                .map_err(|e| format!("{}: {}", cache.dir().display(), e)),
+            None => Ok(()),
         }
     }
 
-    /*pub fn exec(nodes: &[Arc<Node>]) -> Result {
-    }*/
+    /// Sets whether OCCT may run unions and intersections on its own worker threads, from the next
+    /// evaluation on.
+    pub fn set_parallel_booleans(&self, parallel: bool) {
+        self.parallel_booleans.store(parallel, Ordering::Relaxed);
+    }
+
+    pub fn exec(self: &Arc<Self>, nodes: &[Arc<Node>]) -> Result {
+        self.exec_cancellable(nodes, Arc::new(AtomicBool::new(false)))
+    }
+
+    /// Like `exec`, but stops with an error as soon as possible once `cancel` is set.
+    pub fn exec_cancellable(
+        self: &Arc<Self>,
+        nodes: &[Arc<Node>],
+        cancel: Arc<AtomicBool>,
+    ) -> Result {
+        self.log.lock().unwrap().clear();
+
+        for entry in self.cache.write().unwrap().values_mut() {
+            entry.used = false;
+        }
+
+        let env = Arc::new(Env::new(self.clone(), cancel));
+
+        let result = exec_body(
+            env.child(self.builtins.clone()),
+            &[Arc::new(Node::new(
+                0..0,
+                Expr::Call(CallExpr {
+                    name: crate::builtins::ROOT_UNION.to_string(),
+                    args: Vec::new(),
+                    body: nodes.to_vec(),
+                }),
+            ))],
+        );
+
+        self.log
+            .lock()
+            .unwrap()
+            .append(&mut env.log.lock().unwrap());
+
+        // a failed or cancelled evaluation did not visit everything, so only sweep after a
+        // successful one
+        if result.is_ok() {
+            self.cache.write().unwrap().retain(|_, entry| entry.used);
+        }
+
+        result
+    }
+
+    fn cache_get(&self, hash: usize, key: &CacheKey) -> Option<(Value, Vec<String>)> {
+        fn mark_used(cache: &mut HashMap<usize, CacheEntry, FxBuildHasher>, key: usize) {
+            let Some(entry) = cache.get_mut(&key) else {
+                return;
+            };
+            if !entry.used {
+                entry.used = true;
+                for dep in entry.deps.clone() {
+                    mark_used(cache, dep);
+                }
+            }
+        }
+
+        let mut cache = self.cache.write().unwrap();
+        let entry = cache.get(&hash).filter(|entry| entry.key.is_same(key))?;
+        let hit = (entry.value.clone(), entry.log.clone());
+        mark_used(&mut cache, hash);
+
+        Some(hit)
+    }
+
+    fn cache_put(
+        &self,
+        hash: usize,
+        key: CacheKey,
+        value: Value,
+        log: Vec<String>,
+        deps: Vec<usize>,
+    ) {
+        self.cache.write().unwrap().insert(
+            hash,
+            CacheEntry {
+                used: true,
+                key,
+                value,
+                log,
+                deps,
+            },
+        );
+    }
+
+    /// Takes the messages logged by the last execution.
+    pub fn take_log(&self) -> Vec<String> {
+        std::mem::take(&mut self.log.lock().unwrap())
+    }
 }
 
 pub struct CallCtx<'a> {
@@ -36,6 +189,8 @@ pub struct CallCtx<'a> {
     pub named: &'a HashMap<String, Value>,
     pub children: &'a [Value],
     pub is_heavy: bool,
+    runtime: &'a Runtime,
+    log: &'a Mutex<Vec<String>>,
 }
 
 impl CallCtx<'_> {
@@ -43,6 +198,19 @@ impl CallCtx<'_> {
         self.is_heavy = true;
     }
 
+    pub fn log(&self, msg: impl Into<String>) {
+        self.log.lock().unwrap().push(msg.into());
+    }
+
+    pub fn parallel_booleans(&self) -> bool {
+        self.runtime.parallel_booleans.load(Ordering::Relaxed)
+    }
+
+    /// Returns the argument given either by `name` or at position `index`.
+    pub fn arg(&self, index: usize, name: &str) -> Option<&Value> {
+        self.named.get(name).or_else(|| self.pos.get(index))
+    }
+
     pub fn pos_num(&self, index: usize, name: &str) -> std::result::Result<Option<f64>, String> {
         self.pos.get(index).map(|v| v.to_num(name)).transpose()
     }
@@ -50,9 +218,85 @@ impl CallCtx<'_> {
     pub fn named_num(&self, name: &str) -> std::result::Result<Option<f64>, String> {
         self.named.get(name).map(|v| v.to_num(name)).transpose()
     }
+
+    /// Computes a solid through the on-disk cache, keyed by `name` and the arguments of the call.
+    /// Arguments that contain functions cannot be hashed stably between runs, so such calls are
+    /// not cached.
+    pub fn disk_cached(
+        &self,
+        name: &str,
+        f: impl FnOnce() -> std::result::Result<Solid, Box<dyn std::error::Error>>,
+    ) -> std::result::Result<Solid, Box<dyn std::error::Error>> {
+        let args = self
+            .pos
+            .iter()
+            .chain(self.named.values())
+            .chain(self.children);
+        let Some(cache) = self.runtime.disk_cache() else { return f() };
+        if args.clone().any(contains_function) {
+            return f();
+        }
+
+        let mut named = self.named.iter().collect::<Vec<_>>();
+        named.sort_by_key(|(name, _)| *name);
+
+        let mut h = KeyHasher::default();
+        (DISK_CACHE_VERSION, name, self.pos, named, self.children).hash(&mut h);
+        let key = h.key();
+
+        match cache.get(key) {
+            Ok(Some(shape)) => return Ok(Solid::from_hashed_shape(Arc::new(shape), key.hash64())),
+            Ok(None) => {}
+            Err(e) => self.log(format!("Warning: ignoring unreadable cache entry: {}", e)),
+        }
+
+        let solid = f()?;
+        let Some(shape) = solid.as_plain_shape() else { return Ok(solid) };
+
+        if let Err(e) = cache.put(key, shape) {
+            self.log(format!("Warning: failed to write cache entry: {}", e));
+        }
+
+        Ok(Solid::from_hashed_shape(shape.clone(), key.hash64()))
+    }
+
+    pub fn named_bool(&self, name: &str) -> std::result::Result<Option<bool>, String> {
+        self.named.get(name).map(|v| v.to_bool(name)).transpose()
+    }
+
+    /// Returns the positional argument at `index` as a vector of `N` numbers if it is a list.
+    pub fn pos_vec<const N: usize>(
+        &self,
+        index: usize,
+        name: &str,
+    ) -> std::result::Result<Option<[f64; N]>, String> {
+        match self.pos.get(index) {
+            Some(Value::List(items)) if items.len() == N => {
+                let mut v = [0.0; N];
+                for (v, item) in v.iter_mut().zip(items.iter()) {
+                    *v = item.to_num(name)?;
+                }
+
+                Ok(Some(v))
+            }
+            Some(Value::List(_)) => Err(format!("{} must be a vector of {} numbers", name, N)),
+            _ => Ok(None),
+        }
+    }
 }
 
-pub trait BuiltinFunc {
+/// Bump to invalidate existing on-disk cache entries when the meaning of a cached call changes.
+const DISK_CACHE_VERSION: u32 = 1;
+
+fn contains_function(value: &Value) -> bool {
+    match value {
+        Value::BuiltinFunc(_) | Value::Closure(_) => true,
+        Value::List(items) => items.iter().any(contains_function),
+        _ => false,
+    }
+}
+
+pub trait BuiltinFunc: Send + Sync {
     fn is_heavy(&self) -> bool {
         false
     }
@@ -66,6 +310,30 @@ pub struct Error {
     reason: String,
 }
 
+impl Error {
+    /// The node that caused the error.
+    pub fn node(&self) -> &Arc<Node> {
+        &self.node
+    }
+
+    /// Byte range of the offending node in the source code.
+    pub fn pos(&self) -> Range<usize> {
+        self.node.pos.clone()
+    }
+
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.reason)
+    }
+}
+
+impl std::error::Error for Error {}
+
 fn err(node: &Arc<Node>, reason: impl Into<String>) -> Error {
     Error {
         node: node.clone(),
@@ -77,10 +345,25 @@ fn err(node: &Arc<Node>, reason: impl Into<String>) -> Error {
 pub enum Value {
     Undefined,
     Num(f64),
+    Bool(bool),
+    String(Arc<str>),
+    List(Arc<Vec<Value>>),
     BuiltinFunc(Arc<dyn BuiltinFunc>),
+    Closure(Arc<Closure>),
     Solid(Arc<Solid>),
 }
 
+pub struct Closure {
+    name: String,
+    params: Vec<(String, Option<Arc<Node>>)>,
+    body: Vec<Arc<Node>>,
+    env: Arc<Env>,
+    is_heavy: bool,
+    /// The function definition and the values it captures.
+    key: CacheKey,
+    hash: u64,
+}
+
 impl Value {
     pub fn to_num(&self, name: &str) -> std::result::Result<f64, String> {
         match self {
@@ -88,18 +371,147 @@ impl Value {
             _ => Err(format!("{} must be a number", name)),
         }
     }
+
+    pub fn to_bool(&self, name: &str) -> std::result::Result<bool, String> {
+        match self {
+            Value::Bool(b) => Ok(*b),
+            _ => Err(format!("{} must be a boolean", name)),
+        }
+    }
+
+    pub fn to_vec2(&self, name: &str) -> std::result::Result<[f64; 2], String> {
+        match self {
+            Value::List(items) if items.len() == 2 => {
+                Ok([items[0].to_num(name)?, items[1].to_num(name)?])
+            }
+            _ => Err(format!("{} must be a vector of 2 numbers", name)),
+        }
+    }
+
+    pub fn to_vec3(&self, name: &str) -> std::result::Result<[f64; 3], String> {
+        match self {
+            Value::List(items) if (2..=3).contains(&items.len()) => {
+                let mut v = [0.0; 3];
+                for (i, item) in items.iter().enumerate() {
+                    v[i] = item.to_num(name)?;
+                }
+
+                Ok(v)
+            }
+            _ => Err(format!("{} must be a vector of 2 or 3 numbers", name)),
+        }
+    }
+
+    pub fn equals(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Undefined, Value::Undefined) => true,
+            (Value::Num(a), Value::Num(b)) => a == b,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::List(a), Value::List(b)) => {
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| a.equals(b))
+            }
+            (Value::BuiltinFunc(a), Value::BuiltinFunc(b)) => Arc::ptr_eq(a, b),
+            (Value::Closure(a), Value::Closure(b)) => Arc::ptr_eq(a, b),
+            (Value::Solid(a), Value::Solid(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    /// Whether two values are interchangeable as inputs of a cached computation. Unlike `equals`,
+    /// solids and functions built the same way in different evaluations count as the same.
+    fn is_same(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Num(a), Value::Num(b)) => a.to_bits() == b.to_bits(),
+            (Value::List(a), Value::List(b)) => {
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| a.is_same(b))
+            }
+            (Value::Closure(a), Value::Closure(b)) => Arc::ptr_eq(a, b) || a.key.is_same(&b.key),
+            (Value::Solid(a), Value::Solid(b)) => a == b,
+            _ => self.equals(other),
+        }
+    }
+}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, h: &mut H) {
+        std::mem::discriminant(self).hash(h);
+
+        match self {
+            Value::Undefined => {}
+            Value::Num(n) => n.to_bits().hash(h),
+            Value::Bool(b) => b.hash(h),
+            Value::String(s) => s.hash(h),
+            Value::List(items) => items.hash(h),
+            Value::BuiltinFunc(f) => (Arc::as_ptr(f) as *const () as usize).hash(h),
+            Value::Closure(c) => c.hash.hash(h),
+            Value::Solid(s) => s.hash(h),
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Undefined => write!(f, "undefined"),
+            Value::Num(n) => write!(f, "{}", n),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::String(s) => write!(f, "{:?}", s),
+            Value::List(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Value::BuiltinFunc(_) => write!(f, "<builtin function>"),
+            Value::Closure(c) => write!(f, "<function {}>", c.name),
+            Value::Solid(_) => write!(f, "<solid>"),
+        }
+    }
+}
+
+/// A cancellation flag that also counts as set when the flag of the enclosing task or evaluation
+/// is.
+struct Cancel {
+    flag: Arc<AtomicBool>,
+    parent: Option<Arc<Cancel>>,
+}
+
+impl Cancel {
+    fn is_set(&self) -> bool {
+        self.flag.load(Ordering::Relaxed) || self.parent.as_ref().is_some_and(|p| p.is_set())
+    }
 }
 
 struct Env {
     executor: Arc<Runtime>,
+    cancel: Arc<Cancel>,
+    /// Collects the cache keys used while computing a cached value.
+    deps: Option<Arc<Mutex<Vec<usize>>>>,
+    /// Collects the messages logged by the current evaluation, or by a cached value while it is
+    /// computed.
+    log: Arc<Mutex<Vec<String>>>,
+    /// Number of closure calls the current scope is nested in.
+    depth: usize,
     parent: Option<Arc<Env>>,
     vars: HashMap<String, Value>,
 }
 
 impl Env {
-    fn new(executor: Arc<Runtime>) -> Self {
+    fn new(executor: Arc<Runtime>, cancel: Arc<AtomicBool>) -> Self {
         Env {
             executor,
+            cancel: Arc::new(Cancel {
+                flag: cancel,
+                parent: None,
+            }),
+            deps: None,
+            log: Arc::new(Mutex::new(Vec::new())),
+            depth: 0,
             parent: None,
             vars: Default::default(),
         }
@@ -112,48 +524,163 @@ impl Env {
             .or_else(|| self.parent.as_ref().and_then(|p| p.get(name)))
     }
 
+    fn check_cancel(&self, node: &Arc<Node>) -> std::result::Result<(), Error> {
+        if self.cancel.is_set() {
+            Err(err(node, "Cancelled"))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn add_dep(&self, key: usize) {
+        if let Some(deps) = &self.deps {
+            deps.lock().unwrap().push(key);
+        }
+    }
+
     fn child(self: &Arc<Env>, vars: HashMap<String, Value>) -> Arc<Env> {
+        self.child_of(self, self.depth, vars)
+    }
+
+    /// Creates a child scope that belongs to the evaluation `ctx` is part of. Closures use this to
+    /// run in the scope they were defined in, which may be from an earlier evaluation.
+    fn child_of(
+        self: &Arc<Env>,
+        ctx: &Env,
+        depth: usize,
+        vars: HashMap<String, Value>,
+    ) -> Arc<Env> {
         Arc::new(Env {
-            executor: self.executor.clone(),
+            executor: ctx.executor.clone(),
+            cancel: ctx.cancel.clone(),
+            deps: ctx.deps.clone(),
+            log: ctx.log.clone(),
+            depth,
             parent: Some(self.clone()),
             vars,
         })
     }
+
+    /// Creates a scope for computing a cached value, which records its dependencies and messages
+    /// separately.
+    fn with_deps(
+        self: &Arc<Env>,
+        deps: Arc<Mutex<Vec<usize>>>,
+        log: Arc<Mutex<Vec<String>>>,
+    ) -> Arc<Env> {
+        Arc::new(Env {
+            executor: self.executor.clone(),
+            cancel: self.cancel.clone(),
+            deps: Some(deps),
+            log,
+            depth: self.depth,
+            parent: Some(self.clone()),
+            vars: HashMap::new(),
+        })
+    }
+
+    /// Creates a scope for a task, which logs its messages separately and can be cancelled on its
+    /// own.
+    fn for_task(
+        self: &Arc<Env>,
+        cancel: Arc<AtomicBool>,
+        log: Arc<Mutex<Vec<String>>>,
+    ) -> Arc<Env> {
+        Arc::new(Env {
+            executor: self.executor.clone(),
+            cancel: Arc::new(Cancel {
+                flag: cancel,
+                parent: Some(self.cancel.clone()),
+            }),
+            deps: self.deps.clone(),
+            log,
+            depth: self.depth,
+            parent: Some(self.clone()),
+            vars: HashMap::new(),
+        })
+    }
 }
 
-/*impl std::hash::Hash for Expr {
-    fn hash<H: std::hash::Hasher>(&self, h: &mut H) {
-        match self {
-            Expr::Let(n) => {
-                n.name.hash(h);
-                n.value.expr.hash(h);
-                n.body.hash(h);
-            },
+/// What a cached value is computed from: a node and the values of the variables it refers to.
+struct CacheKey {
+    node: Arc<Node>,
+    vars: Vec<Option<Value>>,
+}
+
+impl CacheKey {
+    fn new(env: &Env, node: &Arc<Node>) -> CacheKey {
+        CacheKey {
+            node: node.clone(),
+            vars: node.info.free_vars.iter().map(|name| env.get(name)).collect(),
+        }
+    }
 
-            _ => unimplemented!(),
+    /// Keys that are the same have the same hash, but the hash alone doesn't tell them apart.
+    fn hash64(&self) -> u64 {
+        let mut h = FxHasher64::default();
+        self.node.hash(&mut h);
+        for (name, value) in self.node.info.free_vars.iter().zip(&self.vars) {
+            name.hash(&mut h);
+            value.hash(&mut h);
         }
+
+        h.finish()
     }
-}*/
+
+    fn is_same(&self, other: &CacheKey) -> bool {
+        (Arc::ptr_eq(&self.node, &other.node) || self.node == other.node)
+            && self.vars.len() == other.vars.len()
+            && self.vars.iter().zip(&other.vars).all(|pair| match pair {
+                (Some(a), Some(b)) => a.is_same(b),
+                (None, None) => true,
+                _ => false,
+            })
+    }
+}
 
 fn is_body_heavy(env: &Env, node: &[Arc<Node>]) -> bool {
     node.iter().any(|n| is_node_heavy(env, n))
 }
 
 fn is_node_heavy(env: &Env, node: &Node) -> bool {
-    match &node.expr {
-        Expr::Let(let_) => is_node_heavy(env, &let_.value) || is_body_heavy(env, &let_.body),
-        //Expr::UnOp(op) => is_node_heavy(env, &op.operand),
-        //Expr::BinOp(op) => is_node_heavy(env, &op.left) || is_node_heavy(env, &op.right),
-        Expr::Return(value) => is_node_heavy(env, &value),
-        Expr::Call(call) => match env.get(&call.name) {
-            Some(Value::BuiltinFunc(func)) => func.is_heavy(),
-            _ => false,
-        },
+    node.info.calls.iter().any(|name| match env.get(name) {
+        Some(Value::BuiltinFunc(func)) => func.is_heavy(),
+        Some(Value::Closure(closure)) => closure.is_heavy,
         _ => false,
-    }
+    })
 }
 
 fn exec_expr(env: Arc<Env>, node: &Arc<Node>) -> Result {
+    // heavy calls are memoised so that unchanged parts of the model are not rebuilt on every edit
+    if matches!(node.expr, Expr::Call(_)) && is_node_heavy(&env, node) {
+        let key = CacheKey::new(&env, node);
+        let hash = key.hash64() as usize;
+
+        env.add_dep(hash);
+
+        if let Some((value, mut log)) = env.executor.cache_get(hash, &key) {
+            env.log.lock().unwrap().append(&mut log);
+            return Ok(value);
+        }
+
+        let deps = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let result = exec_expr_uncached(env.with_deps(deps.clone(), log.clone()), node);
+
+        let log = std::mem::take(&mut *log.lock().unwrap());
+        env.log.lock().unwrap().extend(log.iter().cloned());
+
+        let value = result?;
+        let deps = std::mem::take(&mut *deps.lock().unwrap());
+        env.executor.cache_put(hash, key, value.clone(), log, deps);
+
+        return Ok(value);
+    }
+
+    exec_expr_uncached(env, node)
+}
+
+fn exec_expr_uncached(env: Arc<Env>, node: &Arc<Node>) -> Result {
     match &node.expr {
         Expr::Let(let_) => {
             let mut var = HashMap::new();
@@ -165,50 +692,436 @@ fn exec_expr(env: Arc<Env>, node: &Arc<Node>) -> Result {
             .get(&name)
             .ok_or_else(|| err(node, format!("Variable {} does not exist", name))),
         Expr::Num(num) => Ok(Value::Num(*num)),
+        Expr::Str(s) => Ok(Value::String(s.as_str().into())),
+        Expr::List(items) => Ok(Value::List(Arc::new(
+            items
+                .iter()
+                .map(|item| exec_expr(env.clone(), item))
+                .collect::<std::result::Result<Vec<_>, _>>()?,
+        ))),
+        // && and || only evaluate their right operand if the left one doesn't decide the result
+        Expr::Call(call) if matches!(call.name.as_str(), "&&" | "||") => {
+            let operand = |node: &Arc<Node>| {
+                exec_expr(env.clone(), node)?
+                    .to_bool(&format!("Operand of {}", call.name))
+                    .map_err(|e| err(node, e))
+            };
+
+            let decisive = call.name == "||";
+            if operand(&call.args[0].1)? == decisive {
+                Ok(Value::Bool(decisive))
+            } else {
+                Ok(Value::Bool(operand(&call.args[1].1)?))
+            }
+        }
         Expr::Call(call) => {
             let func = env
                 .get(&call.name)
                 .ok_or_else(|| err(node, format!("Function {} does not exist", call.name)))?;
 
-            let Value::BuiltinFunc(func) = func else { return Err(err(node, "")) };
+            let mut pos_args = Vec::new();
+            let mut named_args = HashMap::new();
 
-            let pos_args = call
-                .args
-                .iter()
-                .filter_map(|(name, expr)| name.as_ref().map_or_else(|| Some(expr), |_| None))
-                .map(|expr| exec_expr(env.clone(), &expr))
-                .collect::<std::result::Result<Vec<_>, _>>()?;
+            let args = exec_all(&env, call.args.iter().map(|(_, expr)| expr))?;
+            for ((name, _), val) in call.args.iter().zip(args) {
+                match name {
+                    Some(name) => {
+                        named_args.insert(name.clone(), val);
+                    }
+                    None => pos_args.push(val),
+                }
+            }
 
-            let named_args = call
-                .args
-                .iter()
-                .filter_map(|(name, expr)| name.as_ref().map(|name| (name, expr)))
-                .map(|(name, expr)| exec_expr(env.clone(), expr).map(|val| (name.clone(), val)))
-                .collect::<std::result::Result<HashMap<_, _>, _>>()?;
+            let children = exec_all(&env, &call.body)?;
 
-            let children = call
-                .body
-                .iter()
-                .map(|expr| exec_expr(env.clone(), expr))
-                .collect::<std::result::Result<Vec<_>, _>>()?;
-
-            let mut args = CallCtx {
-                pos: &pos_args,
-                named: &named_args,
-                children: &children,
-                is_heavy: false,
+            env.check_cancel(node)?;
+
+            match func {
+                Value::BuiltinFunc(func) => {
+                    let mut args = CallCtx {
+                        pos: &pos_args,
+                        named: &named_args,
+                        children: &children,
+                        is_heavy: false,
+                        runtime: &env.executor,
+                        log: &env.log,
+                    };
+
+                    func.call(&mut args).map_err(|e| err(node, e.to_string()))
+                }
+                Value::Closure(closure) => {
+                    call_closure(&env, node, &closure, pos_args, named_args, &children)
+                }
+                _ => Err(err(node, format!("{} is not a function", call.name))),
+            }
+        }
+        Expr::If(if_) => {
+            let cond = exec_expr(env.clone(), &if_.cond)?
+                .to_bool("Condition")
+                .map_err(|e| err(&if_.cond, e))?;
+
+            if cond {
+                exec_body(env, &if_.then_body)
+            } else {
+                exec_body(env, if_.else_body.as_deref().unwrap_or(&[]))
+            }
+        }
+        Expr::For(for_) => {
+            let Value::List(items) = exec_expr(env.clone(), &for_.iter)? else {
+                return Err(err(
+                    &for_.iter,
+                    "Only lists and ranges can be iterated over",
+                ));
             };
 
-            func.call(&mut args).map_err(|e| err(node, e.to_string()))
+            let envs = items.iter().map(|item| {
+                let mut var = HashMap::new();
+                var.insert(for_.var.clone(), item.clone());
+                env.child(var)
+            });
+
+            let mut geo = vec![];
+
+            if items.len() > 1 && is_body_heavy(&env, &for_.body) {
+                let tasks = envs
+                    .map(|env| {
+                        let body = for_.body.clone();
+                        Task::spawn(&env, &for_.iter, move |env| exec_body(env, &body))
+                    })
+                    .collect::<Vec<_>>();
+
+                // tasks that are not joined because of an error are cancelled when dropped
+                for task in tasks {
+                    if let Value::Solid(s) = task.join(&env)? {
+                        geo.push(s);
+                    }
+                }
+            } else {
+                for env in envs {
+                    if let Value::Solid(s) = exec_body(env, &for_.body)? {
+                        geo.push(s);
+                    }
+                }
+            }
+
+            Ok(Value::Solid(Arc::new(Solid::combine(
+                geo.iter().map(|s| s.as_ref()),
+            ))))
+        }
+        Expr::Range(range) => Ok(Value::List(Arc::new(
+            range_values(env, node, range)?.map(Value::Num).collect(),
+        ))),
+        Expr::Func(func) => {
+            let key = CacheKey::new(&env, node);
+
+            Ok(Value::Closure(Arc::new(Closure {
+                name: func.name.clone(),
+                params: func.params.clone(),
+                body: func.body.clone(),
+                env: env.clone(),
+                is_heavy: is_body_heavy(&env, &func.body),
+                hash: key.hash64(),
+                key,
+            })))
         }
         Expr::Return(node) => exec_expr(env, node),
     }
 }
 
+/// The most closure calls that may be nested inside each other.
+const MAX_CALL_DEPTH: usize = 500;
+
+/// Stack size for threads that evaluate code, with plenty of room for `MAX_CALL_DEPTH` calls.
+pub const EVAL_STACK_SIZE: usize = 64 << 20;
+
+fn call_closure(
+    caller: &Env,
+    node: &Arc<Node>,
+    closure: &Arc<Closure>,
+    pos_args: Vec<Value>,
+    mut named_args: HashMap<String, Value>,
+    children: &[Value],
+) -> Result {
+    // a runaway recursion would otherwise overflow the stack, which can't be recovered from
+    if caller.depth >= MAX_CALL_DEPTH {
+        return Err(err(node, format!("Recursion too deep in {}", closure.name)));
+    }
+
+    if pos_args.len() > closure.params.len() {
+        return Err(err(
+            node,
+            format!(
+                "{} takes at most {} positional arguments",
+                closure.name,
+                closure.params.len()
+            ),
+        ));
+    }
+
+    if let Some(name) = named_args
+        .keys()
+        .find(|name| !closure.params.iter().any(|(p, _)| p == *name))
+    {
+        return Err(err(
+            node,
+            format!("{} has no parameter named {}", closure.name, name),
+        ));
+    }
+
+    let children = children
+        .iter()
+        .map(|c| match c {
+            Value::Solid(s) => Ok(s.as_ref()),
+            _ => Err(err(node, "Children may only be solids")),
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    let mut vars = HashMap::new();
+    vars.insert(closure.name.clone(), Value::Closure(closure.clone()));
+    vars.insert(
+        "children".to_string(),
+        Value::Solid(Arc::new(Solid::combine(children.into_iter()))),
+    );
+    let mut env = closure.env.child_of(caller, caller.depth + 1, vars);
+
+    // parameters are bound one by one so that defaults may refer to earlier parameters
+    let mut pos_args = pos_args.into_iter();
+    for (name, default) in &closure.params {
+        let value = match (pos_args.next(), named_args.remove(name), default) {
+            (Some(_), Some(_), _) => {
+                return Err(err(
+                    node,
+                    format!("Argument {} given both by position and by name", name),
+                ))
+            }
+            (Some(v), None, _) | (None, Some(v), _) => v,
+            (None, None, Some(default)) => exec_expr(env.clone(), default)?,
+            (None, None, None) => {
+                return Err(err(node, format!("Missing argument {}", name)));
+            }
+        };
+
+        let mut var = HashMap::new();
+        var.insert(name.clone(), value);
+        env = env.child(var);
+    }
+
+    exec_body(env, &closure.body)
+}
+
+/// The most elements a range may have, and the most copies a pattern may make.
+pub const MAX_RANGE_LEN: f64 = 1_000_000.0;
+
+fn range_values(
+    env: Arc<Env>,
+    node: &Arc<Node>,
+    range: &RangeExpr,
+) -> std::result::Result<impl Iterator<Item = f64>, Error> {
+    let num = |n: &Arc<Node>, name: &str| {
+        exec_expr(env.clone(), n)?
+            .to_num(name)
+            .map_err(|e| err(n, e))
+    };
+
+    let start = num(&range.start, "Range start")?;
+    let step = range
+        .step
+        .as_ref()
+        .map(|s| num(s, "Range step"))
+        .transpose()?
+        .unwrap_or(1.0);
+    let end = num(&range.end, "Range end")?;
+
+    // ranges include their end, with a little slack for rounding errors
+    let len = ((end - start) / step + 1e-9).floor() + 1.0;
+    let len = if len.is_finite() { len.max(0.0) } else { 0.0 };
+    if len > MAX_RANGE_LEN {
+        return Err(err(node, format!("Range has too many elements ({})", len)));
+    }
+
+    Ok((0..len as usize).map(move |i| start + i as f64 * step))
+}
+
+/// A piece of work that is sent to the thread pool. If a task has not been started by the time it
+/// is joined, the joining thread runs it itself. This way a task that is waiting for its children
+/// never blocks the pool with work it could do itself.
+struct Task {
+    node: Arc<Node>,
+    state: Mutex<TaskState>,
+    done: Condvar,
+    cancel: Arc<AtomicBool>,
+    /// Messages logged by the task. They are passed on to the joining scope, so that messages end
+    /// up in source order no matter which task finishes first.
+    log: Arc<Mutex<Vec<String>>>,
+}
+
+enum TaskState {
+    Pending(Box<dyn FnOnce() -> Result + Send>),
+    Running,
+    Done(Result),
+}
+
+/// Owns a spawned task. Dropping it without joining cancels the task.
+struct TaskHandle(Arc<Task>);
+
+impl Task {
+    fn spawn(
+        env: &Arc<Env>,
+        node: &Arc<Node>,
+        f: impl FnOnce(Arc<Env>) -> Result + Send + 'static,
+    ) -> TaskHandle {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let task_env = env.for_task(cancel.clone(), log.clone());
+
+        let task = Arc::new(Task {
+            node: node.clone(),
+            state: Mutex::new(TaskState::Pending(Box::new(move || f(task_env)))),
+            done: Condvar::new(),
+            cancel,
+            log,
+        });
+
+        env.executor.pool.execute({
+            let task = task.clone();
+            #[cfg(test)]
+            let executor = env.executor.clone();
+            move || {
+                if task.run() {
+                    #[cfg(test)]
+                    executor.pool_runs.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        });
+
+        TaskHandle(task)
+    }
+
+    /// Runs the task unless it has already been started. Returns whether it was run.
+    fn run(&self) -> bool {
+        let f = {
+            let mut state = self.state.lock().unwrap();
+            match std::mem::replace(&mut *state, TaskState::Running) {
+                TaskState::Pending(f) => f,
+                other => {
+                    *state = other;
+                    return false;
+                }
+            }
+        };
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f))
+            .unwrap_or_else(|_| Err(err(&self.node, "Internal error")));
+
+        *self.state.lock().unwrap() = TaskState::Done(result);
+        self.done.notify_all();
+
+        true
+    }
+}
+
+impl TaskHandle {
+    /// Waits for the task and passes its messages on to `env`.
+    fn join(self, env: &Env) -> Result {
+        let task = &self.0;
+        task.run();
+
+        let state = task.state.lock().unwrap();
+        let mut state = task
+            .done
+            .wait_while(state, |s| matches!(s, TaskState::Running))
+            .unwrap();
+
+        let result = match std::mem::replace(&mut *state, TaskState::Running) {
+            TaskState::Done(result) => result,
+            _ => unreachable!("task joined twice"),
+        };
+
+        env.log
+            .lock()
+            .unwrap()
+            .append(&mut task.log.lock().unwrap());
+
+        result
+    }
+}
+
+impl Drop for TaskHandle {
+    fn drop(&mut self) {
+        self.0.cancel.store(true, Ordering::Relaxed);
+
+        // a task that has not started yet is not run at all
+        let mut state = self.0.state.lock().unwrap();
+        if matches!(*state, TaskState::Pending(_)) {
+            *state = TaskState::Done(Err(err(&self.0.node, "Cancelled")));
+        }
+    }
+}
+
+/// A node that is either evaluated when its result is collected or already running on the thread
+/// pool.
+enum Pending {
+    Inline,
+    Task(TaskHandle),
+}
+
+/// Starts the heavy nodes on the thread pool if there is more than one of them. The results must
+/// then be collected in order with `exec_pending`.
+fn spawn_heavy(env: &Arc<Env>, nodes: &[&Arc<Node>]) -> Vec<Pending> {
+    let heavy = nodes
+        .iter()
+        .map(|n| !matches!(n.expr, Expr::Return(_)) && is_node_heavy(env, n))
+        .collect::<Vec<_>>();
+
+    let parallel = heavy.iter().filter(|h| **h).count() > 1;
+
+    nodes
+        .iter()
+        .zip(heavy)
+        .map(|(node, heavy)| {
+            if parallel && heavy {
+                let task_node = (*node).clone();
+                Pending::Task(Task::spawn(env, node, move |env| {
+                    exec_expr(env, &task_node)
+                }))
+            } else {
+                Pending::Inline
+            }
+        })
+        .collect()
+}
+
+fn exec_pending(env: &Arc<Env>, node: &Arc<Node>, pending: Pending) -> Result {
+    match pending {
+        Pending::Inline => exec_expr(env.clone(), node),
+        Pending::Task(task) => task.join(env),
+    }
+}
+
+/// Evaluates independent expressions, in parallel if more than one of them is heavy. After an
+/// error the remaining tasks are cancelled.
+fn exec_all<'a>(
+    env: &Arc<Env>,
+    nodes: impl IntoIterator<Item = &'a Arc<Node>>,
+) -> std::result::Result<Vec<Value>, Error> {
+    let nodes = nodes.into_iter().collect::<Vec<_>>();
+    let pending = spawn_heavy(env, &nodes);
+
+    nodes
+        .into_iter()
+        .zip(pending)
+        .map(|(node, pending)| exec_pending(env, node, pending))
+        .collect()
+}
+
 fn exec_body(env: Arc<Env>, nodes: &[Arc<Node>]) -> Result {
     let mut geo = vec![];
 
-    for node in nodes {
+    let pending = spawn_heavy(&env, &nodes.iter().collect::<Vec<_>>());
+
+    for (node, pending) in nodes.iter().zip(pending) {
+        env.check_cancel(node)?;
+
         let v = match node.as_ref() {
             Node {
                 expr: Expr::Return(n),
@@ -223,11 +1136,24 @@ fn exec_body(env: Arc<Env>, nodes: &[Arc<Node>]) -> Result {
 
                 return exec_expr(env, n);
             }
-            _ => exec_expr(env.clone(), node)?,
+            _ => exec_pending(&env, node, pending)?,
         };
 
         match v {
             Value::Solid(s) => geo.push(s),
+            // the body of a let and a trailing if may return a value as well
+            v if matches!(node.expr, Expr::Let(_) | Expr::If(_))
+                && Arc::ptr_eq(node, nodes.last().unwrap()) =>
+            {
+                if !geo.is_empty() {
+                    return Err(err(
+                        node,
+                        "A body may either produce geometry or return a value, but not both",
+                    ));
+                }
+
+                return Ok(v);
+            }
             _ => {}
         }
     }
@@ -237,18 +1163,372 @@ fn exec_body(env: Arc<Env>, nodes: &[Arc<Node>]) -> Result {
     ))))
 }
 
-pub fn exec(nodes: &[Arc<Node>]) -> Result {
-    let env = Arc::new(Env::new(Arc::new(Runtime::new())));
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
 
-    exec_body(
-        env.child(crate::builtins::get_builtins()),
-        &[Arc::new(Node {
-            pos: 0..0,
-            expr: Expr::Call(CallExpr {
-                name: "union".to_string(),
-                args: Vec::new(),
-                body: nodes.to_vec(),
-            }),
-        })],
-    )
+    fn eval_with_runtime(runtime: &Arc<Runtime>, code: &str) -> Result {
+        let (_, body) = crate::parser::parse_source(code).expect("parse failed");
+        let env = Arc::new(Env::new(runtime.clone(), Arc::new(AtomicBool::new(false))));
+
+        let result = exec_body(env.child(runtime.builtins.clone()), &body);
+        runtime
+            .log
+            .lock()
+            .unwrap()
+            .append(&mut env.log.lock().unwrap());
+
+        result
+    }
+
+    pub(crate) fn eval(code: &str) -> Result {
+        eval_with_runtime(&Arc::new(Runtime::new()), code)
+    }
+
+    /// Evaluates `code` as a whole program and returns the resulting shape.
+    pub(crate) fn eval_shape(code: &str) -> Arc<pollocad_occt::Shape> {
+        let (_, body) = crate::parser::parse_source(code).expect("parse failed");
+        match Arc::new(Runtime::new()).exec(&body) {
+            Ok(Value::Solid(s)) => s.get_single_shape().expect("no shape"),
+            Ok(_) => panic!("{} did not evaluate to a solid", code),
+            Err(e) => panic!("{} failed: {:?}", code, e.reason),
+        }
+    }
+
+    pub(crate) fn assert_bounds(code: &str, expected: [f64; 6]) {
+        let b = eval_shape(code).bounds();
+        let actual = [b.xmin, b.ymin, b.zmin, b.xmax, b.ymax, b.zmax];
+        assert!(
+            actual
+                .iter()
+                .zip(expected)
+                .all(|(a, e)| (a - e).abs() < 1e-3),
+            "{}: bounds are {:?}, expected {:?}",
+            code,
+            actual,
+            expected
+        );
+    }
+
+    pub(crate) fn assert_volume(code: &str, expected: f64) {
+        let volume = eval_shape(code).volume();
+        assert!(
+            (volume - expected).abs() <= expected * 0.01 + 1e-6,
+            "{}: volume is {}, expected {}",
+            code,
+            volume,
+            expected
+        );
+    }
+
+    pub(crate) fn assert_solid(code: &str) {
+        assert!(matches!(eval(code), Ok(Value::Solid(_))), "{} failed", code);
+    }
+
+    pub(crate) fn eval_num(code: &str) -> f64 {
+        match eval(code) {
+            Ok(Value::Num(n)) => n,
+            Ok(_) => panic!("{} did not evaluate to a number", code),
+            Err(e) => panic!("{} failed: {:?}", code, e.reason),
+        }
+    }
+
+    #[test]
+    fn test_let() {
+        assert_eq!(eval_num("x = 1; x"), 1.0);
+        assert_eq!(eval_num("x = 1; y = x + 1; y * 2"), 4.0);
+        assert!(eval("cube(); x = 1; x").is_err());
+    }
+
+    #[test]
+    fn test_if() {
+        assert_eq!(eval_num("x = 3; if (x > 2) { 1 } else { 2 }"), 1.0);
+        assert_eq!(eval_num("x = 1; if (x > 2) { 1 } else if (x > 0) { 2 } else { 3 }"), 2.0);
+        assert_eq!(eval_num("y = if (!(1 < 2)) { 1 } else { -4 }; y * 2"), -8.0);
+        assert!(matches!(eval("if (true) { 1 }"), Ok(Value::Num(_))));
+        assert!(matches!(eval("if (false) { 1 }"), Ok(Value::Solid(_))));
+        assert!(eval("if (1) { 1 }").is_err());
+        assert!(eval("cube(); if (true) { 1 }").is_err());
+        assert!(matches!(eval("cube(); if (true) { cube(); }"), Ok(Value::Solid(_))));
+    }
+
+    #[test]
+    fn test_short_circuit() {
+        let bool = |code: &str| match eval(code) {
+            Ok(Value::Bool(b)) => b,
+            _ => panic!("{} did not evaluate to a boolean", code),
+        };
+
+        // the right operand would fail if it were evaluated
+        assert!(!bool("v = [1]; i = 1; i < 1 && v[i] > 0"));
+        assert!(bool("v = [1]; i = 1; i >= 1 || v[i] > 0"));
+        assert!(bool("v = [1]; i = 0; i < 1 && v[i] > 0"));
+        assert!(bool("fn f(n) { n <= 0 || f(n - 1) } f(3)"));
+        assert!(eval("v = [1]; i = 0; i < 1 && v[1] > 0").is_err());
+        assert!(eval("1 && true").is_err());
+        assert!(eval("false || 1").is_err());
+    }
+
+    #[test]
+    fn test_closure() {
+        assert_eq!(eval_num("fn f(a, b = a * 2) { a + b } f(1) + f(1, 1) + f(b=3, a=1)"), 9.0);
+        assert_eq!(eval_num("fn fact(n) { if (n <= 1) { 1 } else { n * fact(n - 1) } } fact(5)"), 120.0);
+        assert_eq!(eval_num("x = 10; fn f() { x } x = 20; f()"), 10.0);
+        assert_eq!(eval_num("fn adder(n) { fn add(x) { x + n } add } add2 = adder(2); add2(3)"), 5.0);
+        assert!(matches!(eval("fn part() { translate(x=1) children; } part() cube();"), Ok(Value::Solid(_))));
+        assert!(eval("fn f(a) { a } f()").is_err());
+        assert!(eval("fn f(a) { a } f(1, 2)").is_err());
+        assert!(eval("fn f(a) { a } f(1, a=2)").is_err());
+        assert!(eval("fn f(a) { a } f(b=2)").is_err());
+        assert!(eval("x = 1; x()").is_err());
+    }
+
+    #[test]
+    fn test_recursion_limit() {
+        assert_eq!(eval_num("fn f(n) { if (n > 0) { f(n - 1) + 1 } else { 0 } } f(200)"), 200.0);
+        match eval("fn f(n) { f(n + 1) } f(0)") {
+            Err(e) => assert_eq!(e.reason, "Recursion too deep in f"),
+            Ok(_) => panic!("runaway recursion did not fail"),
+        }
+        assert!(eval("fn f(n) { translate(x=1) f(n + 1); } f(0);").is_err());
+    }
+
+    #[test]
+    fn test_list() {
+        assert_eq!(eval_num("v = [1, [2, 3]]; v[1][0] + v[0]"), 3.0);
+        assert_eq!(eval_num("[0:2:10][3]"), 6.0);
+        assert_eq!(
+            eval_num("if ([1, [2]] == [1, [2]] && [1] != [2]) { 1 } else { 0 }"),
+            1.0
+        );
+        assert_eq!(eval_num("fn sum(v, i = 0) { if (i < 3) { v[i] + sum(v, i + 1) } else { 0 } } sum([1, 2, 3])"), 6.0);
+        assert!(eval("[1, 2][2]").is_err());
+        assert!(eval("[1, 2][0.5]").is_err());
+        assert!(eval("x = 1; x[0]").is_err());
+        assert!(matches!(
+            eval("for (p = [[0, 0, 0], [1, 2, 0]]) translate(p) cube([1, 2, 3]);"),
+            Ok(Value::Solid(_))
+        ));
+        assert!(eval("for (x = 3) cube();").is_err());
+    }
+
+    #[test]
+    fn test_echo() {
+        let runtime = Arc::new(Runtime::new());
+        eval_with_runtime(
+            &runtime,
+            r#"x = 2; echo("x is", x, [x, "two"], b=x == 2, a=true); echo();"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            runtime.take_log(),
+            vec![
+                r#"ECHO: x is, 2, [2, "two"], a = true, b = true"#,
+                "ECHO: ",
+            ]
+        );
+        assert!(runtime.take_log().is_empty());
+
+        // a cache hit logs the messages of the calls it skips
+        let (_, body) = crate::parser::parse_source(
+            r#"fn part(i) { echo("part", i); cube(i); } part(1); translate(x=5) part(2);"#,
+        )
+        .unwrap();
+        for _ in 0..2 {
+            runtime.exec(&body).unwrap();
+            assert_eq!(runtime.take_log(), vec!["ECHO: part, 1", "ECHO: part, 2"]);
+        }
+    }
+
+    #[test]
+    fn test_parallel() {
+        let runtime = Arc::new(Runtime::new());
+
+        let code = r#"
+            fn part(i) { translate(x=i) union() { cube(); cylinder(r=1, h=i + 1); } }
+            union() {
+                for (i = [0:20]) part(i);
+                union() { cube(5); anti() cube(1); }
+                translate([1, 2, 3]) cube();
+            }
+        "#;
+
+        for _ in 0..10 {
+            assert!(matches!(
+                eval_with_runtime(&runtime, code),
+                Ok(Value::Solid(_))
+            ));
+        }
+        assert!(runtime.pool_runs.load(Ordering::Relaxed) > 0);
+
+        // messages are logged in source order as well
+        eval_with_runtime(
+            &runtime,
+            r#"union() { for (i = [0:7]) { echo(i); cube(i + 1); } }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            runtime.take_log(),
+            (0..8).map(|i| format!("ECHO: {}", i)).collect::<Vec<_>>()
+        );
+
+        // errors are reported in source order even if a later task finishes first
+        for _ in 0..10 {
+            let e = eval_with_runtime(
+                &runtime,
+                r#"union() { for (i = [0:20]) cube(); cube(x="a"); cylinder(h="b"); }"#,
+            )
+            .err()
+            .expect("invalid cube succeeded");
+            assert_eq!(e.reason(), "x must be a number");
+        }
+    }
+
+    #[test]
+    fn test_booleans() {
+        let overlapping = "cube([10, 10, 10]); translate([5, 5, 5]) cube([10, 10, 10]);";
+        assert_volume(overlapping, 2000.0 - 125.0);
+        assert_bounds(overlapping, [0.0, 0.0, 0.0, 15.0, 15.0, 15.0]);
+
+        // every other slab is cut out of the cube
+        assert_volume(
+            "cube([10, 10, 10]); for (i = [0:4]) translate(x=2 * i) anti() cube([1, 10, 10]);",
+            500.0,
+        );
+
+        let three = "intersection() { cube([10, 10, 10]); translate(x=5) cube([10, 10, 10]); \
+                     translate(y=5) cube([10, 10, 10]); }";
+        assert_volume(three, 250.0);
+        assert_bounds(three, [5.0, 5.0, 0.0, 10.0, 10.0, 10.0]);
+        assert_volume(
+            "intersection() { cube(); translate(x=5) cube(); translate(y=5) cube(); }",
+            0.0,
+        );
+
+        // a child that is empty makes the whole intersection empty
+        for code in [
+            "intersection() { cube(); if (false) { cube(); } }",
+            "intersection() { cube(); for (i = [0:-1]) cube(); }",
+            "intersection() { cube(); anti() cube(); }",
+        ] {
+            assert!(
+                matches!(eval(code), Ok(Value::Solid(s)) if s.get_single_shape().is_none()),
+                "{} is not empty",
+                code
+            );
+        }
+
+        // OCCT's own threads give the same results
+        let runtime = Arc::new(Runtime::new());
+        runtime.set_parallel_booleans(true);
+        for (code, expected) in [(overlapping, 1875.0), (three, 250.0)] {
+            let (_, body) = crate::parser::parse_source(code).unwrap();
+            let Ok(Value::Solid(s)) = runtime.exec(&body) else {
+                panic!("{} failed", code)
+            };
+            let volume = s.get_single_shape().unwrap().volume();
+            assert!(
+                (volume - expected).abs() < 0.01 * expected,
+                "{}: volume is {}",
+                code,
+                volume
+            );
+        }
+    }
+
+    #[test]
+    fn test_cache() {
+        let runtime = Arc::new(Runtime::new());
+        let exec = |code: &str| {
+            let (_, body) = crate::parser::parse_source(code).unwrap();
+            match runtime.exec(&body) {
+                Ok(Value::Solid(s)) => s.get_single_shape().unwrap(),
+                _ => panic!("{} did not evaluate to a solid", code),
+            }
+        };
+        let cached = || {
+            let cache = runtime.cache.read().unwrap();
+            cache.values().map(|e| e.value.clone()).collect::<Vec<_>>()
+        };
+
+        let a = exec("x = 1; fn part() { cube(x); } translate(x=5) part(); cylinder();");
+        let a_cached = cached();
+        assert!(a_cached.len() > 1);
+
+        // moving code around does not change the hash, and a hit keeps the entries it was
+        // computed from
+        let b = exec("x = 1;\n\nfn part() { cube(x); }\ntranslate(x=5) part();\ncylinder();");
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!(cached().len(), a_cached.len());
+
+        // an edit only recomputes what changed, here the cylinder and the top level union
+        let c = exec("x = 1; fn part() { cube(x); } translate(x=5) part(); cylinder(h=2);");
+        assert!(!Arc::ptr_eq(&a, &c));
+        let c_cached = cached();
+        assert_eq!(c_cached.len(), a_cached.len());
+        assert_eq!(
+            c_cached
+                .iter()
+                .filter(|v| a_cached.iter().any(|a| a.equals(v)))
+                .count(),
+            a_cached.len() - 2
+        );
+
+        // changing a captured variable invalidates the closure call as well, leaving only the
+        // cylinder
+        exec("x = 2; fn part() { cube(x); } translate(x=5) part(); cylinder(h=2);");
+        let d_cached = cached();
+        assert_eq!(d_cached.len(), a_cached.len());
+        assert_eq!(
+            d_cached
+                .iter()
+                .filter(|v| c_cached.iter().any(|c| c.equals(v)))
+                .count(),
+            1
+        );
+
+        // keys whose hashes collide are told apart
+        let (_, body) = crate::parser::parse_source("cube(); sphere();").unwrap();
+        let env = Env::new(runtime.clone(), Arc::new(AtomicBool::new(false)));
+        runtime.cache_put(0, CacheKey::new(&env, &body[0]), Value::Num(1.0), vec![], vec![]);
+        assert!(runtime.cache_get(0, &CacheKey::new(&env, &body[1])).is_none());
+        assert!(runtime.cache_get(0, &CacheKey::new(&env, &body[0])).is_some());
+    }
+
+    #[test]
+    fn test_cancel() {
+        let (_, body) = crate::parser::parse_source("cube();").unwrap();
+        let runtime = Arc::new(Runtime::new());
+
+        let cancel = Arc::new(AtomicBool::new(true));
+        let e = runtime
+            .exec_cancellable(&body, cancel.clone())
+            .err()
+            .expect("cancelled exec succeeded");
+        assert_eq!(e.reason(), "Cancelled");
+
+        cancel.store(false, Ordering::Relaxed);
+        assert!(runtime.exec_cancellable(&body, cancel).is_ok());
+    }
+
+    #[test]
+    fn test_range() {
+        let values = |code: &str| match eval(code) {
+            Ok(Value::List(items)) => Ok(items
+                .iter()
+                .map(|v| v.to_num("").unwrap())
+                .collect::<Vec<_>>()),
+            Ok(_) => panic!("{} did not evaluate to a list", code),
+            Err(e) => Err(e.reason),
+        };
+
+        assert_eq!(values("[0:3]"), Ok(vec![0.0, 1.0, 2.0, 3.0]));
+        assert_eq!(values("[1:0.5:2.1]"), Ok(vec![1.0, 1.5, 2.0]));
+        assert_eq!(values("[0:0.1:0.3]").unwrap().len(), 4);
+        assert_eq!(values("[3:-1:1]"), Ok(vec![3.0, 2.0, 1.0]));
+        assert_eq!(values("[3:1]"), Ok(vec![]));
+        assert_eq!(values("[0:0:1]"), Ok(vec![]));
+        assert!(values("[0:1e9]").is_err());
+    }
 }
@@ -0,0 +1,54 @@
+use std::io::{self, Write};
+
+use pollocad_occt::Mesh;
+
+fn triangles(mesh: &Mesh) -> impl Iterator<Item = ([f32; 3], [[f32; 3]; 3])> + '_ {
+    mesh.triangles.iter().map(|t| {
+        let [a, b, c] = t.map(|i| mesh.vertices[i as usize]);
+
+        let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+        let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+        let n = [
+            u[1] * v[2] - u[2] * v[1],
+            u[2] * v[0] - u[0] * v[2],
+            u[0] * v[1] - u[1] * v[0],
+        ];
+        let len = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+        let n = if len > 0.0 { n.map(|x| x / len) } else { [0.0; 3] };
+
+        (n.map(|x| x as f32), [a, b, c].map(|p| p.map(|x| x as f32)))
+    })
+}
+
+pub fn write_binary(w: &mut impl Write, mesh: &Mesh) -> io::Result<()> {
+    w.write_all(&[0u8; 80])?;
+    w.write_all(&(mesh.triangles.len() as u32).to_le_bytes())?;
+
+    for (normal, verts) in triangles(mesh) {
+        for v in std::iter::once(normal).chain(verts) {
+            for x in v {
+                w.write_all(&x.to_le_bytes())?;
+            }
+        }
+
+        w.write_all(&[0u8; 2])?;
+    }
+
+    Ok(())
+}
+
+pub fn write_ascii(w: &mut impl Write, mesh: &Mesh) -> io::Result<()> {
+    writeln!(w, "solid pollocad")?;
+
+    for (n, verts) in triangles(mesh) {
+        writeln!(w, "  facet normal {:e} {:e} {:e}", n[0], n[1], n[2])?;
+        writeln!(w, "    outer loop")?;
+        for v in verts {
+            writeln!(w, "      vertex {:e} {:e} {:e}", v[0], v[1], v[2])?;
+        }
+        writeln!(w, "    endloop")?;
+        writeln!(w, "  endfacet")?;
+    }
+
+    writeln!(w, "endsolid pollocad")
+}
@@ -0,0 +1,183 @@
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::time::Instant;
+
+use pollocad_occt::Shape;
+
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::disk_cache::DiskCache;
+use crate::parser;
+use crate::runtime::{Runtime, Value, EVAL_STACK_SIZE};
+
+struct Job {
+    generation: u64,
+    source: String,
+    cancel: Arc<AtomicBool>,
+}
+
+/// The result of evaluating one version of the source code.
+pub struct Output {
+    pub generation: u64,
+    /// `None` if evaluation failed or produced no solid.
+    pub shape: Option<Arc<Shape>>,
+    pub log: Vec<String>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Evaluates source code on a background thread. Every submission gets a new generation number,
+/// and submitting new code cancels the evaluation in flight so that only the latest result is ever
+/// returned.
+pub struct Worker {
+    runtime: Arc<Runtime>,
+    generation: u64,
+    cancel: Arc<AtomicBool>,
+    started: Option<Instant>,
+    jobs: Sender<Job>,
+    results: Receiver<Output>,
+}
+
+impl Worker {
+    /// Starts the worker thread. `notify` is called from the worker thread whenever a result is
+    /// ready.
+    pub fn new(notify: impl Fn() + Send + 'static) -> Worker {
+        let (jobs, job_rx) = mpsc::channel::<Job>();
+        let (result_tx, results) = mpsc::channel();
+
+        let runtime = Arc::new(Runtime::with_disk_cache(
+            DiskCache::default_dir().map(DiskCache::new),
+        ));
+        let thread_runtime = runtime.clone();
+
+        std::thread::Builder::new()
+            .name("pollocad-worker".to_string())
+            .stack_size(EVAL_STACK_SIZE)
+            .spawn(move || {
+                while let Ok(mut job) = job_rx.recv() {
+                    // skip straight to the newest job if more have queued up
+                    while let Ok(newer) = job_rx.try_recv() {
+                        job = newer;
+                    }
+
+                    if job.cancel.load(Ordering::Relaxed) {
+                        continue;
+                    }
+
+                    // a panic must not take the thread down, or no later submission would ever
+                    // get a result
+                    let output =
+                        panic::catch_unwind(AssertUnwindSafe(|| evaluate(&thread_runtime, &job)))
+                            .unwrap_or_else(|payload| panicked(&thread_runtime, &job, payload));
+
+                    if result_tx.send(output).is_err() {
+                        break;
+                    }
+
+                    notify();
+                }
+            })
+            .expect("failed to spawn worker thread");
+
+        Worker {
+            runtime,
+            generation: 0,
+            cancel: Arc::new(AtomicBool::new(false)),
+            started: None,
+            jobs,
+            results,
+        }
+    }
+
+    /// Starts evaluating new source code, superseding any evaluation in flight.
+    pub fn submit(&mut self, source: String) {
+        self.cancel.store(true, Ordering::Relaxed);
+
+        self.generation += 1;
+        self.cancel = Arc::new(AtomicBool::new(false));
+        self.started = Some(Instant::now());
+
+        // if the worker thread has died, there is nothing to report results anyway
+        let _ = self.jobs.send(Job {
+            generation: self.generation,
+            source,
+            cancel: self.cancel.clone(),
+        });
+    }
+
+    /// Returns the result of the latest submission if it has finished.
+    pub fn poll(&mut self) -> Option<Output> {
+        let output = self
+            .results
+            .try_iter()
+            .filter(|o| o.generation == self.generation)
+            .last()?;
+
+        self.started = None;
+
+        Some(output)
+    }
+
+    /// Sets whether results are stored in and read from the on-disk cache.
+    pub fn set_disk_cache_enabled(&self, enabled: bool) {
+        self.runtime.set_disk_cache_enabled(enabled);
+    }
+
+    /// Removes every entry from the on-disk cache.
+    pub fn clear_disk_cache(&self) -> Result<(), String> {
+        self.runtime.clear_disk_cache()
+    }
+
+    /// Sets whether OCCT may run unions and intersections on its own worker threads.
+    pub fn set_parallel_booleans(&self, parallel: bool) {
+        self.runtime.set_parallel_booleans(parallel);
+    }
+
+    /// Returns the time the evaluation in flight was started, if any.
+    pub fn busy_since(&self) -> Option<Instant> {
+        self.started
+    }
+}
+
+fn evaluate(runtime: &Arc<Runtime>, job: &Job) -> Output {
+    let mut shape = None;
+    let mut diagnostics = Vec::new();
+
+    match parser::parse_source(&job.source) {
+        Ok((_, body)) => match runtime.exec_cancellable(body.as_ref(), job.cancel.clone()) {
+            Ok(Value::Solid(geo)) => shape = geo.get_single_shape(),
+            Ok(_) => {}
+            Err(e) => diagnostics.push(Diagnostic::from_exec_error(&job.source, &e)),
+        },
+        Err(e) => diagnostics.push(Diagnostic::from_parse_error(&job.source, &e)),
+    }
+
+    Output {
+        generation: job.generation,
+        shape,
+        log: runtime.take_log(),
+        diagnostics,
+    }
+}
+
+/// The output for a job whose evaluation panicked, with the panic message as a diagnostic.
+fn panicked(runtime: &Arc<Runtime>, job: &Job, payload: Box<dyn Any + Send>) -> Output {
+    let reason = payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string());
+
+    Output {
+        generation: job.generation,
+        shape: None,
+        log: runtime.take_log(),
+        diagnostics: vec![Diagnostic::new(
+            &job.source,
+            0..0,
+            format!("Internal error: {}", reason),
+            Severity::Error,
+        )],
+    }
+}